@@ -1,5 +1,6 @@
 use std::{
-    collections::{BTreeSet, HashMap},
+    cmp::Ordering,
+    collections::{BTreeSet, BinaryHeap, HashMap},
     marker::PhantomData,
     pin::Pin,
     process::Output,
@@ -8,13 +9,21 @@ use std::{
     time::Duration,
 };
 
-use async_compression::futures::write::ZstdDecoder;
+use async_compression::futures::{
+    bufread::{
+        Lz4Decoder as Lz4BufDecoder, SnappyDecoder as SnappyBufDecoder,
+        ZstdDecoder as ZstdBufDecoder,
+    },
+    write::{Lz4Decoder, SnappyDecoder, ZstdDecoder, ZstdEncoder},
+};
 use engine_traits::{
     CfName, ExternalSstFileInfo, SstCompressionType, SstExt, SstMetaInfo, SstWriter,
     SstWriterBuilder,
 };
 use external_storage::ExternalStorage;
-use futures::io::{AllowStdIo, AsyncReadExt, AsyncWriteExt, Cursor};
+use fst::{IntoStreamer, Streamer};
+use futures::io::{AllowStdIo, AsyncRead as FAsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, Cursor};
+use tempfile::NamedTempFile;
 use tikv_util::{
     codec::{
         self,
@@ -183,12 +192,47 @@ impl<S: Stream<Item = Result<LogFile>>> Stream for CollectCompaction<S> {
     }
 }
 
+/// Compression codec for a log file or compacted SST, mirroring the knobs
+/// `async_compression` exposes (an absent level falls back to the codec's
+/// default).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CompressionCodec {
+    None,
+    Zstd(Option<i32>),
+    Lz4(Option<i32>),
+    Snappy,
+}
+
+impl Default for CompressionCodec {
+    fn default() -> Self {
+        CompressionCodec::Zstd(None)
+    }
+}
+
+impl CompressionCodec {
+    fn as_sst_compression(&self) -> Option<SstCompressionType> {
+        match self {
+            CompressionCodec::None => None,
+            CompressionCodec::Zstd(_) => Some(SstCompressionType::Zstd),
+            CompressionCodec::Lz4(_) => Some(SstCompressionType::Lz4),
+            CompressionCodec::Snappy => Some(SstCompressionType::Snappy),
+        }
+    }
+
+    fn level(&self) -> Option<i32> {
+        match self {
+            CompressionCodec::Zstd(l) | CompressionCodec::Lz4(l) => *l,
+            CompressionCodec::None | CompressionCodec::Snappy => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 struct Source {
     inner: Arc<dyn ExternalStorage>,
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
 struct Record {
     prefix: Arc<[u8]>,
     key: Vec<u8>,
@@ -205,36 +249,138 @@ impl Record {
                 .then(self.key.cmp(&other.key))
         }
     }
+
+    /// Approximate heap footprint of this record, used to decide when the
+    /// in-memory sort buffer should be spilled.
+    fn encoded_len(&self) -> usize {
+        self.key.len() + self.value.len()
+    }
+
+    /// The MVCC commit ts this record was written at, decoded from the
+    /// trailing 8 bytes of the full (prefix-restored) key: TiKV appends a
+    /// bit-complemented, big-endian ts suffix to every key so that, for a
+    /// given user key, newer versions sort first. A key too short to carry
+    /// a ts suffix (shouldn't happen for real log data) yields `None`.
+    fn ts(&self) -> Option<u64> {
+        let full_len = self.prefix.len() + self.key.len();
+        if full_len < 8 {
+            return None;
+        }
+        let mut suffix = [0u8; 8];
+        let from_prefix = 8usize.saturating_sub(self.key.len());
+        suffix[..from_prefix]
+            .copy_from_slice(&self.prefix[self.prefix.len() - from_prefix..]);
+        suffix[from_prefix..].copy_from_slice(&self.key[self.key.len() - (8 - from_prefix)..]);
+        Some(!u64::from_be_bytes(suffix))
+    }
+}
+
+/// Target size of the decompressed window [`Source::load`] keeps resident
+/// at once. The window grows past this only if a single event does not
+/// fit in it. Deliberately small relative to [`DEFAULT_SORT_BUFFER_SIZE`]:
+/// it bounds per-file memory, not the whole compaction's working set,
+/// and a load concurrency of 16 multiplies it.
+const STREAM_WINDOW_SIZE: usize = ReadableSize::kb(256).0 as usize;
+
+/// Computes how much of `window` lies at or before the end of `borrowed`,
+/// a slice known to have been borrowed from `window`. [`stream_event`]'s
+/// `EventIterator` hands back `key()`/`value()` as slices into the buffer
+/// it was built over, so this is how [`Source::load`] learns how many
+/// bytes of the window an event consumed without a position API.
+fn offset_past(window: &[u8], borrowed: &[u8]) -> usize {
+    (borrowed.as_ptr() as usize + borrowed.len()) - window.as_ptr() as usize
 }
 
 impl Source {
     async fn load(
         &self,
         id: LogFileId,
+        default_codec: CompressionCodec,
         mut stat: Option<&mut LoadStatistic>,
         mut on_key_value: impl FnMut(&[u8], &[u8]),
     ) -> Result<()> {
-        let mut content = vec![];
-        let mut decompress = ZstdDecoder::new(Cursor::new(&mut content));
+        // Mixed-codec backups are expected: a file carries its own codec
+        // metadata, falling back to the caller-configured default for files
+        // written before that metadata existed.
+        let codec = id.compression.unwrap_or(default_codec);
         let source = self.inner.read_part(&id.name, id.offset, id.length);
-        let n = futures::io::copy(source, &mut decompress).await?;
-        stat.as_mut().map(|stat| stat.physical_bytes_in += n);
-        decompress.flush().await?;
-        drop(decompress);
+        let mut reader: Pin<Box<dyn FAsyncRead + Send>> = match codec {
+            CompressionCodec::None => Box::pin(source),
+            CompressionCodec::Zstd(_) => Box::pin(ZstdBufDecoder::new(BufReader::new(source))),
+            CompressionCodec::Lz4(_) => Box::pin(Lz4BufDecoder::new(BufReader::new(source))),
+            CompressionCodec::Snappy => Box::pin(SnappyBufDecoder::new(BufReader::new(source))),
+        };
 
         let mut co = Cooperate::new(4096);
-        let mut iter = stream_event::EventIterator::new(&content);
-        iter.next()?;
-        while iter.valid() {
-            co.step().await;
-            on_key_value(iter.key(), iter.value());
-            stat.as_mut().map(|stat| {
-                stat.keys_in += 1;
-                stat.logical_key_bytes_in += iter.key().len() as u64;
-                stat.logical_value_bytes_in += iter.value().len() as u64;
-            });
+        // Decompressed bytes not yet fully consumed by an event; refilled
+        // from `reader` and drained from the front as events are emitted,
+        // so this never holds more than one window's worth (plus, rarely,
+        // whatever a single oversized event needs) of the decompressed
+        // file at once.
+        let mut window: Vec<u8> = Vec::new();
+        let mut target = STREAM_WINDOW_SIZE;
+        let mut physical_bytes_in = 0u64;
+        let mut reader_done = false;
+
+        loop {
+            if !reader_done && window.len() < target {
+                let start = window.len();
+                window.resize(target, 0);
+                let n = reader.read(&mut window[start..]).await?;
+                window.truncate(start + n);
+                physical_bytes_in += n as u64;
+                reader_done = n == 0;
+            }
+            if window.is_empty() {
+                break;
+            }
+
+            let mut iter = stream_event::EventIterator::new(&window);
+            let mut consumed = 0;
             iter.next()?;
+            while iter.valid() {
+                // Unless this is the final window, hold back an event that
+                // runs all the way to the end of the buffer: it might be
+                // whole, or it might be truncated by the window boundary
+                // and about to be completed by the next refill.
+                if !reader_done && offset_past(&window, iter.value()) == window.len() {
+                    break;
+                }
+                co.step().await;
+                on_key_value(iter.key(), iter.value());
+                stat.as_mut().map(|stat| {
+                    stat.keys_in += 1;
+                    stat.logical_key_bytes_in += iter.key().len() as u64;
+                    stat.logical_value_bytes_in += iter.value().len() as u64;
+                });
+                consumed = offset_past(&window, iter.value());
+                iter.next()?;
+            }
+
+            if consumed == 0 {
+                if reader_done {
+                    // A non-empty tail remains but nothing in it parsed as
+                    // a whole event even with no more data coming: corrupt
+                    // or truncated input.
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "log file ended with a truncated event",
+                    )
+                    .into());
+                }
+                // No event fit in the window at all; it holds one
+                // oversized record in progress. Grow it and keep reading.
+                target *= 2;
+                continue;
+            }
+            window.drain(..consumed);
+            target = STREAM_WINDOW_SIZE;
+            if reader_done && window.is_empty() {
+                break;
+            }
         }
+
+        stat.as_mut().map(|stat| stat.physical_bytes_in += physical_bytes_in);
         stat.as_mut().map(|stat| stat.files_in += 1);
         Ok(())
     }
@@ -245,6 +391,13 @@ pub struct CompactWorker<DB> {
     output: Arc<dyn ExternalStorage>,
     max_load_concurrency: usize,
     co: Cooperate,
+    /// Target size, in bytes, of a single output SST; `write_sst` rolls
+    /// over to a fresh writer once the current one's estimated size crosses
+    /// this. Zero means "never roll over", i.e. always emit a single SST.
+    target_sst_size: usize,
+    /// Accumulates manifest entries and merged statistics across every
+    /// `compact_ext` call since the last [`CompactWorker::flush_manifest`].
+    manifest: CompactionManifest,
 
     // Note: maybe use the TiKV config to construct a DB?
     _great_phantom: PhantomData<DB>,
@@ -255,6 +408,16 @@ pub struct CompactLogExt<'a> {
     pub load_statistic: Option<&'a mut LoadStatistic>,
     pub compact_statistic: Option<&'a mut CompactStatistic>,
     pub max_load_concurrency: usize,
+    /// Byte-size threshold for the in-memory run buffer kept by the
+    /// external merge sort in [`CompactWorker::load`]; once an accumulating
+    /// run crosses this, it is sorted, deduplicated and spilled to a
+    /// temporary file. Zero uses [`DEFAULT_SORT_BUFFER_SIZE`].
+    pub sort_buffer_size: usize,
+    /// Codec assumed for a source log file that carries no compression
+    /// metadata of its own.
+    pub default_input_compression: CompressionCodec,
+    /// Codec and level used when writing the compacted output SST.
+    pub output_compression: CompressionCodec,
 }
 
 impl<'a> CompactLogExt<'a> {
@@ -280,12 +443,21 @@ impl<DB> CompactWorker<DB> {
             output: storage,
             max_load_concurrency: 16,
             co: Cooperate::new(4096),
+            target_sst_size: 0,
+            manifest: CompactionManifest::default(),
             _great_phantom: PhantomData,
         }
     }
+
+    /// Sets the target size of a single output SST; `write_sst` splits the
+    /// compaction's output across multiple SSTs once a writer crosses this.
+    pub fn with_target_sst_size(mut self, target_sst_size: usize) -> Self {
+        self.target_sst_size = target_sst_size;
+        self
+    }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct LoadStatistic {
     pub files_in: u64,
     pub keys_in: u64,
@@ -304,17 +476,25 @@ impl LoadStatistic {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct CompactStatistic {
     pub keys_out: u64,
     pub physical_bytes_out: u64,
     pub logical_key_bytes_out: u64,
     pub logical_value_bytes_out: u64,
+    /// Bytes spent on the sparse FST key index sidecar (see
+    /// [`SstKeyIndex`]), on top of `physical_bytes_out`.
+    pub index_bytes_out: u64,
 
     pub write_sst_duration: Duration,
     pub load_duration: Duration,
     pub sort_duration: Duration,
     pub save_duration: Duration,
+
+    /// Codec the output SST was written with, so callers can compute a
+    /// compression ratio from `physical_bytes_out` and the logical byte
+    /// counts above.
+    pub output_codec: Option<CompressionCodec>,
 }
 
 impl CompactStatistic {
@@ -323,10 +503,350 @@ impl CompactStatistic {
         self.physical_bytes_out += other.physical_bytes_out;
         self.logical_key_bytes_out += other.logical_key_bytes_out;
         self.logical_value_bytes_out += other.logical_value_bytes_out;
+        self.index_bytes_out += other.index_bytes_out;
         self.write_sst_duration += other.write_sst_duration;
         self.load_duration += other.load_duration;
         self.sort_duration += other.sort_duration;
         self.save_duration += other.save_duration;
+        if other.output_codec.is_some() {
+            self.output_codec = other.output_codec;
+        }
+    }
+}
+
+/// Everything a restore job needs to know about one output SST without
+/// opening it: its key range, ts span and where it came from.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CompactionManifestEntry {
+    pub name: String,
+    /// Name of this entry's sparse FST key index sidecar (see
+    /// [`SstKeyIndex`]), written alongside `name` in the same storage.
+    pub index_name: String,
+    pub cf: String,
+    pub region_id: u64,
+    pub min_key: Vec<u8>,
+    pub max_key: Vec<u8>,
+    pub min_ts: u64,
+    pub max_ts: u64,
+    pub file_size: u64,
+    pub num_entries: u64,
+}
+
+/// Accumulates the output of many [`CompactWorker::compact_ext`] calls so a
+/// single manifest, describing every SST produced by a restore batch, can
+/// be flushed once at the end. A restorer reads this to plan ingestion and
+/// skip irrelevant files without opening any SST.
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CompactionManifest {
+    pub entries: Vec<CompactionManifestEntry>,
+    pub load_statistic: LoadStatistic,
+    pub compact_statistic: CompactStatistic,
+}
+
+/// Number of records between consecutive samples in the sparse FST key
+/// index `write_sst` builds for each output SST. Deliberately sparse: the
+/// index only needs to narrow a lookup to a nearby record ordinal, not
+/// pinpoint it, so a wide stride keeps the sidecar small.
+const FST_SAMPLE_INTERVAL: u64 = 64;
+
+/// Incrementally builds the sparse FST key index sidecar for a single
+/// output SST. Keys must be inserted in strictly increasing order, which
+/// `write_sst` already guarantees since it consumes the sorted merged
+/// stream.
+struct FstIndexBuilder {
+    builder: fst::MapBuilder<Vec<u8>>,
+    seen: u64,
+}
+
+impl FstIndexBuilder {
+    fn new() -> Self {
+        Self {
+            // A `Vec<u8>` writer cannot fail, so construction cannot either.
+            builder: fst::MapBuilder::new(Vec::new()).expect("in-memory fst writer cannot fail"),
+            seen: 0,
+        }
+    }
+
+    /// Samples `key` at `ordinal` if it falls on the sampling stride.
+    fn maybe_insert(&mut self, key: &[u8], ordinal: u64) -> Result<()> {
+        if self.seen % FST_SAMPLE_INTERVAL == 0 {
+            self.builder
+                .insert(key, ordinal)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        }
+        self.seen += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<u8>> {
+        Ok(self
+            .builder
+            .into_inner()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?)
+    }
+}
+
+/// Outcome of a [`SstKeyIndex`] lookup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyLookup {
+    /// No sampled key is compatible with `key`'s being in range: the SST
+    /// cannot contain it.
+    DefinitelyAbsent,
+    /// `key` falls within the sampled range; decompression, if needed,
+    /// can start near this record ordinal instead of at the beginning.
+    PossiblyPresent { near_ordinal: u64 },
+}
+
+/// A sparse, in-memory FST mapping a sampled subset of one output SST's
+/// keys to their record ordinal. Loaded from the `.sst.fst` sidecar
+/// `write_sst` produces, it lets a restore job rule out (or narrow the
+/// search for) a key without decompressing the SST itself.
+pub struct SstKeyIndex {
+    map: fst::Map<Vec<u8>>,
+}
+
+impl SstKeyIndex {
+    /// Loads an index previously produced by `write_sst` from its raw
+    /// sidecar bytes.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        let map =
+            fst::Map::new(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(Self { map })
+    }
+
+    /// Looks up `key` against the sampled keys. A sample at or below `key`
+    /// is proof that it is at least *possible* the key is present nearby;
+    /// no such sample proves it is not.
+    pub fn lookup(&self, key: &[u8]) -> KeyLookup {
+        if let Some(ordinal) = self.map.get(key) {
+            return KeyLookup::PossiblyPresent {
+                near_ordinal: ordinal,
+            };
+        }
+        let mut nearest = None;
+        let mut stream = self.map.range().lt(key).into_stream();
+        while let Some((_, ordinal)) = stream.next() {
+            nearest = Some(ordinal);
+        }
+        match nearest {
+            Some(near_ordinal) => KeyLookup::PossiblyPresent { near_ordinal },
+            None => KeyLookup::DefinitelyAbsent,
+        }
+    }
+}
+
+/// Default byte-size threshold for a single externally-sorted run, used
+/// when [`CompactLogExt::sort_buffer_size`] is left at zero. Deliberately a
+/// fraction of the 128MB compaction-collection threshold so a single
+/// compaction's working set typically spills into a handful of runs rather
+/// than none or hundreds.
+const DEFAULT_SORT_BUFFER_SIZE: usize = ReadableSize::mb(32).0 as usize;
+
+/// Accumulates [`Record`]s in an in-memory run, transparently spilling a
+/// sorted, deduplicated run to a temporary Zstd-compressed file whenever the
+/// run crosses `sort_buffer_size` bytes. Finishing returns every spilled run
+/// alongside the final, still-in-memory tail run, ready to be k-way merged.
+struct SpillBuffer {
+    buffer: Vec<Record>,
+    buffer_bytes: usize,
+    sort_buffer_size: usize,
+    spills: Vec<NamedTempFile>,
+}
+
+impl SpillBuffer {
+    fn new(sort_buffer_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            buffer_bytes: 0,
+            sort_buffer_size,
+            spills: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, record: Record) -> Result<()> {
+        self.buffer_bytes += record.encoded_len();
+        self.buffer.push(record);
+        if self.buffer_bytes >= self.sort_buffer_size {
+            self.flush_run()?;
+        }
+        Ok(())
+    }
+
+    fn flush_run(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut run = std::mem::take(&mut self.buffer);
+        self.buffer_bytes = 0;
+        run.sort_unstable_by(Record::cmp_key);
+        run.dedup_by(|a, b| a.cmp_key(b) == Ordering::Equal);
+        self.spills.push(spill_run_to_disk(&run)?);
+        Ok(())
+    }
+
+    /// Sorts and deduplicates the final, still-buffered tail and returns it
+    /// alongside every previously spilled run.
+    fn finish(mut self) -> Result<(Vec<NamedTempFile>, Vec<Record>)> {
+        let mut tail = std::mem::take(&mut self.buffer);
+        tail.sort_unstable_by(Record::cmp_key);
+        tail.dedup_by(|a, b| a.cmp_key(b) == Ordering::Equal);
+        Ok((self.spills, tail))
+    }
+}
+
+/// Writes an already-sorted-and-deduplicated run to a temporary file as a
+/// Zstd-compressed sequence of length-prefixed `(key, value)` pairs.
+fn spill_run_to_disk(records: &[Record]) -> Result<NamedTempFile> {
+    let mut compressed = vec![];
+    block_on_external_io(async {
+        let mut encoder = ZstdEncoder::new(Cursor::new(&mut compressed));
+        for r in records {
+            encoder.write_all(&(r.key.len() as u32).to_be_bytes()).await?;
+            encoder.write_all(&r.key).await?;
+            encoder
+                .write_all(&(r.value.len() as u32).to_be_bytes())
+                .await?;
+            encoder.write_all(&r.value).await?;
+        }
+        encoder.flush().await?;
+        encoder.close().await?;
+        Result::Ok(())
+    })?;
+    let mut file = NamedTempFile::new()?;
+    std::io::Write::write_all(&mut file, &compressed)?;
+    Ok(file)
+}
+
+/// One run being merged: either a spilled, Zstd-compressed temporary file
+/// read back incrementally, or the final in-memory tail run.
+enum SpillRun {
+    File {
+        decoder: ZstdBufDecoder<BufReader<AllowStdIo<std::fs::File>>>,
+        prefix: Arc<[u8]>,
+    },
+    Memory(std::vec::IntoIter<Record>),
+}
+
+impl SpillRun {
+    fn open(path: &std::path::Path, prefix: Arc<[u8]>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let reader = BufReader::new(AllowStdIo::new(file));
+        Ok(SpillRun::File {
+            decoder: ZstdBufDecoder::new(reader),
+            prefix,
+        })
+    }
+
+    async fn next(&mut self) -> Result<Option<Record>> {
+        match self {
+            SpillRun::Memory(iter) => Ok(iter.next()),
+            SpillRun::File { decoder, prefix } => {
+                let mut len_buf = [0u8; 4];
+                if let Err(e) = decoder.read_exact(&mut len_buf).await {
+                    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                        return Ok(None);
+                    }
+                    return Err(e.into());
+                }
+                let key_len = u32::from_be_bytes(len_buf) as usize;
+                let mut key = vec![0u8; key_len];
+                decoder.read_exact(&mut key).await?;
+                decoder.read_exact(&mut len_buf).await?;
+                let value_len = u32::from_be_bytes(len_buf) as usize;
+                let mut value = vec![0u8; value_len];
+                decoder.read_exact(&mut value).await?;
+                Ok(Some(Record {
+                    prefix: prefix.clone(),
+                    key,
+                    value,
+                }))
+            }
+        }
+    }
+}
+
+/// One candidate record in the k-way merge heap, ordered so that
+/// `BinaryHeap` (a max-heap) pops the record with the smallest `cmp_key`
+/// first.
+struct HeapItem {
+    record: Record,
+    run_idx: usize,
+}
+
+impl PartialEq for HeapItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.record.cmp_key(&other.record) == Ordering::Equal
+    }
+}
+impl Eq for HeapItem {}
+impl PartialOrd for HeapItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for HeapItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.record.cmp_key(&self.record)
+    }
+}
+
+/// A lazily-pulled, fully sorted and deduplicated merge of every spilled run
+/// plus the final in-memory tail, fed directly into [`CompactWorker::write_sst`].
+/// Peak memory is bounded by one decode buffer per run (the run heads held
+/// in `heap`) rather than the whole compaction.
+struct MergedStream {
+    runs: Vec<SpillRun>,
+    heap: BinaryHeap<HeapItem>,
+    last_emitted: Option<Record>,
+    primed: bool,
+    // Keeps the spilled files alive for the lifetime of the merge; they are
+    // cleaned up once this (and thus the `NamedTempFile`s) drops.
+    _spills: Vec<NamedTempFile>,
+}
+
+impl MergedStream {
+    fn new(runs: Vec<SpillRun>, spills: Vec<NamedTempFile>) -> Self {
+        Self {
+            runs,
+            heap: BinaryHeap::new(),
+            last_emitted: None,
+            primed: false,
+            _spills: spills,
+        }
+    }
+
+    async fn prime(&mut self) -> Result<()> {
+        for idx in 0..self.runs.len() {
+            if let Some(record) = self.runs[idx].next().await? {
+                self.heap.push(HeapItem { record, run_idx: idx });
+            }
+        }
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Result<Option<Record>> {
+        if !self.primed {
+            self.prime().await?;
+            self.primed = true;
+        }
+        loop {
+            let Some(HeapItem { record, run_idx }) = self.heap.pop() else {
+                return Ok(None);
+            };
+            if let Some(next_record) = self.runs[run_idx].next().await? {
+                self.heap.push(HeapItem {
+                    record: next_record,
+                    run_idx,
+                });
+            }
+            if let Some(last) = &self.last_emitted
+                && last.cmp_key(&record) == Ordering::Equal
+            {
+                continue;
+            }
+            self.last_emitted = Some(record.clone());
+            return Ok(Some(record));
+        }
     }
 }
 
@@ -334,32 +854,31 @@ impl<DB: SstExt> CompactWorker<DB>
 where
     <<DB as SstExt>::SstWriter as SstWriter>::ExternalSstFileReader: 'static,
 {
-    const COMPRESSION: Option<SstCompressionType> = Some(SstCompressionType::Lz4);
-
-    async fn merge_and_sort(&mut self, items: impl Iterator<Item = Vec<Record>>) -> Vec<Record> {
-        let mut flatten_items = items
-            .into_iter()
-            .flat_map(|v| v.into_iter())
-            .collect::<Vec<_>>();
-        flatten_items.sort_unstable_by(|k1, k2| k1.cmp_key(&k2));
-        tokio::task::yield_now().await;
-        flatten_items.dedup_by(|k1, k2| k1.cmp_key(&k2) == std::cmp::Ordering::Equal);
-        flatten_items
+    /// Opens every spilled run plus the in-memory tail as a single lazily
+    /// pulled, fully sorted and deduplicated stream.
+    fn merge_sorted_runs(
+        &mut self,
+        (spills, tail): (Vec<NamedTempFile>, Vec<Record>),
+        common_prefix: Arc<[u8]>,
+    ) -> Result<MergedStream> {
+        let mut runs = Vec::with_capacity(spills.len() + 1);
+        for spill in &spills {
+            runs.push(SpillRun::open(spill.path(), common_prefix.clone())?);
+        }
+        runs.push(SpillRun::Memory(tail.into_iter()));
+        Ok(MergedStream::new(runs, spills))
     }
 
     async fn load(
         &mut self,
         c: &Compaction,
+        common_prefix: Arc<[u8]>,
         ext: &mut CompactLogExt<'_>,
-    ) -> Result<impl Iterator<Item = Vec<Record>>> {
+    ) -> Result<(Vec<NamedTempFile>, Vec<Record>)> {
         let mut eext = ExecuteAllExt::default();
         let load_stat = ext.load_statistic.is_some();
         eext.max_concurrency = ext.max_load_concurrency;
 
-        let common_prefix_len = common_prefix_len(&c.min_key, &c.max_key);
-        let common_prefix =
-            Arc::<[u8]>::from(c.min_key[..common_prefix_len].to_vec().into_boxed_slice());
-
         let items = super::util::execute_all_ext(
             c.source
                 .iter()
@@ -367,11 +886,12 @@ where
                 .map(|f| {
                     let source = &self.source;
                     let common_prefix = common_prefix.clone();
+                    let default_codec = ext.default_input_compression;
                     Box::pin(async move {
                         let mut out = vec![];
                         let mut stat = LoadStatistic::default();
                         source
-                            .load(f, load_stat.then_some(&mut stat), |k, v| {
+                            .load(f, default_codec, load_stat.then_some(&mut stat), |k, v| {
                                 out.push(Record {
                                     prefix: common_prefix.clone(),
                                     key: k.strip_prefix(common_prefix.as_ref()).unwrap().to_owned(),
@@ -387,30 +907,64 @@ where
         )
         .await?;
 
-        let mut result = Vec::with_capacity(items.len());
+        let sort_buffer_size = if ext.sort_buffer_size == 0 {
+            DEFAULT_SORT_BUFFER_SIZE
+        } else {
+            ext.sort_buffer_size
+        };
+        let mut spill = SpillBuffer::new(sort_buffer_size);
         for (item, stat) in items {
             ext.with_load_stat(|s| s.merge_with(&stat));
-            result.push(item);
+            for record in item {
+                spill.push(record)?;
+            }
         }
-        Ok(result.into_iter())
+        spill.finish()
     }
 
+    fn open_sst_writer(&self, cf: CfName, codec: CompressionCodec) -> Result<DB::SstWriter> {
+        let mut builder = <DB as SstExt>::SstWriterBuilder::new()
+            .set_cf(cf)
+            .set_compression_type(codec.as_sst_compression())
+            .set_in_memory(true);
+        if let Some(level) = codec.level() {
+            builder = builder.set_compression_level(level);
+        }
+        Ok(builder.build(&"in-mem.sst")?)
+    }
+
+    /// Writes the merged, sorted stream out as one or more SSTs, never
+    /// splitting a key across two files: a writer only rolls over to a
+    /// fresh SST once a full key-value pair has been written and the
+    /// writer's estimated size has crossed `target_sst_size`. Returns the
+    /// produced SSTs in the same key order they were written in, each
+    /// paired with the actual `(min_ts, max_ts)` span of the records it
+    /// holds — tracked per file alongside `min_key`/`max_key`, rather than
+    /// reusing the whole compaction's overall span for every split file.
     async fn write_sst(
         &mut self,
         cf: CfName,
-        sorted_items: impl Iterator<Item = Record>,
+        mut sorted_items: MergedStream,
         ext: &mut CompactLogExt<'_>,
-    ) -> Result<(impl ExternalSstFileInfo, impl std::io::Read + 'static)> {
-        let mut w = <DB as SstExt>::SstWriterBuilder::new()
-            .set_cf(cf)
-            .set_compression_type(Self::COMPRESSION)
-            .set_in_memory(true)
-            .build(&"in-mem.sst")?;
+    ) -> Result<Vec<(impl ExternalSstFileInfo, impl std::io::Read + 'static, Vec<u8>, u64, u64)>>
+    {
+        let codec = ext.output_compression;
+        let mut outputs = vec![];
+        let mut w = self.open_sst_writer(cf, codec)?;
+        let mut writer_has_entries = false;
+        let mut index = FstIndexBuilder::new();
 
         let mut key_buf = vec![];
         let mut last_prefix = None;
-        for mut item in sorted_items {
+        let mut ordinal: u64 = 0;
+        let mut min_ts = u64::MAX;
+        let mut max_ts = 0u64;
+        while let Some(mut item) = sorted_items.next().await? {
             self.co.step().await;
+            if let Some(ts) = item.ts() {
+                min_ts = min_ts.min(ts);
+                max_ts = max_ts.max(ts);
+            }
             if last_prefix == Some(Arc::as_ptr(&item.prefix)) {
                 key_buf.truncate(item.prefix.len());
             } else {
@@ -419,49 +973,151 @@ where
             }
             key_buf.append(&mut item.key);
             w.put(&key_buf, &item.value)?;
+            writer_has_entries = true;
+            index.maybe_insert(&key_buf, ordinal)?;
+            ordinal += 1;
             ext.with_compact_stat(|stat| {
                 stat.logical_key_bytes_out += key_buf.len() as u64;
                 stat.logical_value_bytes_out += item.value.len() as u64;
-            })
+            });
+
+            if self.target_sst_size > 0 && w.file_size() >= self.target_sst_size as u64 {
+                let (info, out) = w.finish_read()?;
+                let index_bytes = std::mem::replace(&mut index, FstIndexBuilder::new()).finish()?;
+                ext.with_compact_stat(|stat| {
+                    stat.keys_out += info.num_entries();
+                    stat.physical_bytes_out += info.file_size();
+                    stat.index_bytes_out += index_bytes.len() as u64;
+                    stat.output_codec = Some(codec);
+                });
+                outputs.push((info, out, index_bytes, min_ts, max_ts));
+                w = self.open_sst_writer(cf, codec)?;
+                writer_has_entries = false;
+                last_prefix = None;
+                ordinal = 0;
+                min_ts = u64::MAX;
+                max_ts = 0;
+            }
+        }
+        if writer_has_entries || outputs.is_empty() {
+            let (info, out) = w.finish_read()?;
+            let index_bytes = index.finish()?;
+            ext.with_compact_stat(|stat| {
+                stat.keys_out += info.num_entries();
+                stat.physical_bytes_out += info.file_size();
+                stat.index_bytes_out += index_bytes.len() as u64;
+                stat.output_codec = Some(codec);
+            });
+            outputs.push((info, out, index_bytes, min_ts, max_ts));
         }
-        let (info, out) = w.finish_read()?;
-        ext.with_compact_stat(|stat| {
-            stat.keys_out += info.num_entries();
-            stat.physical_bytes_out += info.file_size();
-        });
 
-        Ok((info, out))
+        Ok(outputs)
     }
 
     pub async fn compact_ext(&mut self, c: Compaction, mut ext: CompactLogExt<'_>) -> Result<()> {
         let mut eext = ExecuteAllExt::default();
         eext.max_concurrency = ext.max_load_concurrency;
 
+        // Tracked separately from the caller-supplied `ext` statistics so
+        // this call's contribution to `self.manifest` can be merged exactly
+        // once, regardless of whether `ext` holds a fresh pair of stats or
+        // ones the caller has been accumulating across many calls.
+        let mut call_load_stat = LoadStatistic::default();
+        let mut call_compact_stat = CompactStatistic::default();
+        let mut call_ext = CompactLogExt {
+            load_statistic: Some(&mut call_load_stat),
+            compact_statistic: Some(&mut call_compact_stat),
+            max_load_concurrency: ext.max_load_concurrency,
+            sort_buffer_size: ext.sort_buffer_size,
+            default_input_compression: ext.default_input_compression,
+            output_compression: ext.output_compression,
+        };
+
+        let common_prefix_len = common_prefix_len(&c.min_key, &c.max_key);
+        let common_prefix =
+            Arc::<[u8]>::from(c.min_key[..common_prefix_len].to_vec().into_boxed_slice());
+
         let begin = Instant::now();
-        let items = self.load(&c, &mut ext).await?;
-        ext.with_compact_stat(|stat| stat.load_duration += begin.saturating_elapsed());
+        let runs = self.load(&c, common_prefix.clone(), &mut call_ext).await?;
+        call_ext.with_compact_stat(|stat| stat.load_duration += begin.saturating_elapsed());
 
         let begin = Instant::now();
-        let sorted_items = self.merge_and_sort(items).await;
-        ext.with_compact_stat(|stat| stat.sort_duration += begin.saturating_elapsed());
+        let sorted_items = self.merge_sorted_runs(runs, common_prefix)?;
+        call_ext.with_compact_stat(|stat| stat.sort_duration += begin.saturating_elapsed());
 
         let begin = Instant::now();
-        let (info, out) = self
-            .write_sst(c.cf, sorted_items.into_iter(), &mut ext)
-            .await?;
-        ext.with_compact_stat(|stat| stat.write_sst_duration += begin.saturating_elapsed());
+        let outputs = self.write_sst(c.cf, sorted_items, &mut call_ext).await?;
+        call_ext.with_compact_stat(|stat| stat.write_sst_duration += begin.saturating_elapsed());
 
         let begin = Instant::now();
-        let out_name = format!("{}-{}-{}.sst", c.region_id, c.min_ts, c.max_ts);
+        for (seq, (info, out, index_bytes, file_min_ts, file_max_ts)) in
+            outputs.into_iter().enumerate()
+        {
+            // An output file with no decodable ts (e.g. empty) falls back to
+            // the whole compaction's span rather than an inverted range.
+            let (min_ts, max_ts) = if file_min_ts <= file_max_ts {
+                (file_min_ts, file_max_ts)
+            } else {
+                (c.min_ts, c.max_ts)
+            };
+            let out_name = format!("{}-{}-{}-{}.sst", c.region_id, c.min_ts, c.max_ts, seq);
+            let index_name = format!("{}.fst", out_name);
+            self.manifest.entries.push(CompactionManifestEntry {
+                name: out_name.clone(),
+                index_name: index_name.clone(),
+                cf: c.cf.to_owned(),
+                region_id: c.region_id,
+                min_key: info.smallest_key().to_vec(),
+                max_key: info.largest_key().to_vec(),
+                min_ts,
+                max_ts,
+                file_size: info.file_size(),
+                num_entries: info.num_entries(),
+            });
+            self.output
+                .write(
+                    &out_name,
+                    external_storage::UnpinReader(Box::new(AllowStdIo::new(out))),
+                    info.file_size(),
+                )
+                .await?;
+            let index_len = index_bytes.len() as u64;
+            self.output
+                .write(
+                    &index_name,
+                    external_storage::UnpinReader(Box::new(Cursor::new(index_bytes))),
+                    index_len,
+                )
+                .await?;
+        }
+        call_ext.with_compact_stat(|stat| stat.save_duration += begin.saturating_elapsed());
+
+        ext.with_load_stat(|stat| stat.merge_with(&call_load_stat));
+        ext.with_compact_stat(|stat| stat.merge_with(&call_compact_stat));
+        self.manifest.load_statistic.merge_with(&call_load_stat);
+        self.manifest.compact_statistic.merge_with(&call_compact_stat);
+        Ok(())
+    }
+
+    /// Serializes every manifest entry and merged statistic accumulated
+    /// since the last call (or since this worker was created) to a single
+    /// JSON manifest object named `{batch_name}.manifest.json` in the
+    /// output storage, then resets the accumulator. A restore job can read
+    /// just this file to plan ingestion and skip irrelevant SSTs without
+    /// opening any of them.
+    pub async fn flush_manifest(&mut self, batch_name: &str) -> Result<CompactionManifest> {
+        let manifest = std::mem::take(&mut self.manifest);
+        let content = serde_json::to_vec(&manifest)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let len = content.len() as u64;
         self.output
             .write(
-                &out_name,
-                external_storage::UnpinReader(Box::new(AllowStdIo::new(out))),
-                info.file_size(),
+                &format!("{}.manifest.json", batch_name),
+                external_storage::UnpinReader(Box::new(Cursor::new(content))),
+                len,
             )
             .await?;
-        ext.with_compact_stat(|stat| stat.save_duration += begin.saturating_elapsed());
-        Ok(())
+        Ok(manifest)
     }
 }
 
@@ -471,4 +1127,4 @@ fn common_prefix_len(k1: &[u8], k2: &[u8]) -> usize {
         n += 1;
     }
     n
-}
\ No newline at end of file
+}
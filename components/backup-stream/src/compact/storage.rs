@@ -0,0 +1,37 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Identifies and describes a single backup-stream log file in external
+//! storage, as read and folded into a [`Compaction`](super::compaction::Compaction)
+//! by [`CollectCompaction`](super::compaction::CollectCompaction).
+
+use std::sync::Arc;
+
+use super::compaction::CompressionCodec;
+
+/// Identifies one segment of a log file in external storage: which object,
+/// and which byte range within it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LogFileId {
+    pub name: String,
+    pub offset: u64,
+    pub length: u64,
+    /// The codec this segment was written with, if known. `None` for log
+    /// files written before per-file codec metadata existed; [`Source::load`]
+    /// falls back to a caller-configured default in that case.
+    pub compression: Option<CompressionCodec>,
+}
+
+/// Metadata describing one source log file, as handed to
+/// [`CollectCompaction`](super::compaction::CollectCompaction) to be grouped
+/// into region/CF compactions.
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    pub id: LogFileId,
+    pub region_id: u64,
+    pub cf: &'static str,
+    pub real_size: u64,
+    pub min_ts: u64,
+    pub max_ts: u64,
+    pub min_key: Arc<[u8]>,
+    pub max_key: Arc<[u8]>,
+}
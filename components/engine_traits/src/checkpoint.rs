@@ -10,6 +10,67 @@ pub trait Checkpointable {
     fn new_checkpointer(&self) -> Result<Self::Checkpointer>;
 
     fn merge(&self, dbs: &[&Self]) -> Result<()>;
+
+    /// Like `merge`, but partitions `dbs` into work units merged concurrently
+    /// on a bounded thread pool instead of a single serial
+    /// `merge_instances` call. See `MergeOpts` for how the number of tasks
+    /// and their size are derived.
+    fn merge_with_opts(&self, dbs: &[&Self], opts: MergeOpts) -> Result<()> {
+        // Default implementation: degrade to the single-shot path. Engines
+        // that can benefit from partitioned merges should override this.
+        let _ = opts;
+        self.merge(dbs)
+    }
+}
+
+/// Tuning knobs for `Checkpointable::merge_with_opts`.
+#[derive(Debug, Clone, Copy)]
+pub struct MergeOpts {
+    /// Upper bound on the number of concurrent merge tasks.
+    pub max_threads: usize,
+    /// A task won't be split further once its share of the total on-disk
+    /// size drops to or below this many bytes.
+    pub min_bytes_per_task: u64,
+}
+
+impl Default for MergeOpts {
+    fn default() -> Self {
+        MergeOpts {
+            max_threads: 1,
+            min_bytes_per_task: u64::MAX,
+        }
+    }
+}
+
+impl MergeOpts {
+    /// Scales `max_threads` to the machine's core count instead of the
+    /// single-threaded `default()`, so `merge_with_opts` actually benefits
+    /// from `task_count_for_merge`'s size-based split.
+    pub fn adaptive(min_bytes_per_task: u64) -> Self {
+        let max_threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        MergeOpts {
+            max_threads,
+            min_bytes_per_task,
+        }
+    }
+}
+
+/// Below this size, splitting a merge across threads costs more in thread
+/// spin-up and lock contention than it saves; used by `Checkpointable::merge`
+/// to pick `MergeOpts::adaptive`'s size threshold.
+pub const DEFAULT_MIN_BYTES_PER_MERGE_TASK: u64 = 64 * 1024 * 1024;
+
+/// Splits `total_bytes` worth of work across up to `max_threads` tasks, each
+/// at least `min_bytes_per_task` large, mirroring the "compute chunk size
+/// from input data size and thread count" heuristic.
+pub fn task_count_for_merge(total_bytes: u64, opts: &MergeOpts) -> usize {
+    if opts.max_threads <= 1 || total_bytes == 0 {
+        return 1;
+    }
+    let by_size = (total_bytes / opts.min_bytes_per_task.max(1)).max(1);
+    by_size.min(opts.max_threads as u64) as usize
 }
 
 pub trait Checkpointer {
@@ -23,6 +84,19 @@ pub trait Checkpointer {
     fn column_family_meta_data(&self, _cf: CfName) -> Result<ColumnFamilyMetadata> {
         unimplemented!()
     }
+
+    /// Builds the integrity manifest (a Merkle root plus per-leaf metadata)
+    /// over every SST file tracked by `column_family_meta_data`. Should be
+    /// called right after `create_at` succeeds.
+    fn build_manifest(&self, _cf: CfName) -> Result<CheckpointManifest> {
+        unimplemented!()
+    }
+
+    /// Returns an inclusion proof for `file_name`, verifiable without access
+    /// to the rest of the checkpoint.
+    fn generate_proof(&self, _cf: CfName, _file_name: &str) -> Result<MerkleProof> {
+        unimplemented!()
+    }
 }
 
 pub struct SstFileInfo {
@@ -38,6 +112,207 @@ pub struct ColumnFamilyMetadata {
     pub ssts: Vec<BTreeMap<Vec<u8>, SstFileInfo>>,
 }
 
+/// A 32-byte digest used as both a leaf and an internal node of the
+/// checkpoint's Merkle tree.
+pub type MerkleHash = [u8; 32];
+
+const ZERO_HASH: MerkleHash = [0u8; 32];
+
+fn hash_leaf(data: &[u8]) -> MerkleHash {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]); // leaf domain separator
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+fn hash_branch(left: &MerkleHash, right: &MerkleHash) -> MerkleHash {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]); // branch domain separator
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Metadata recorded alongside every leaf of the Merkle tree, so a failed
+/// proof can be mapped back to the concrete SST that produced it.
+#[derive(Debug, Clone)]
+pub struct ManifestLeaf {
+    pub cf: String,
+    pub level: usize,
+    pub file_name: String,
+    pub leaf_index: usize,
+    pub end_key: Vec<u8>,
+    pub hash: MerkleHash,
+}
+
+/// A single sibling on the authentication path from a leaf to the root.
+/// `left` is `true` when the sibling is the left child, i.e. the leaf's
+/// hash should be combined as the right operand.
+#[derive(Debug, Clone, Copy)]
+pub struct ProofStep {
+    pub sibling: MerkleHash,
+    pub left: bool,
+}
+
+/// An inclusion proof for a single leaf, verifiable without the rest of the
+/// checkpoint.
+#[derive(Debug, Clone)]
+pub struct MerkleProof {
+    pub leaf: ManifestLeaf,
+    pub steps: Vec<ProofStep>,
+}
+
+impl MerkleProof {
+    /// Recomputes the root from the leaf hash and the authentication path,
+    /// and compares it against `root`.
+    pub fn verify(&self, root: &MerkleHash) -> bool {
+        let mut acc = self.leaf.hash;
+        for step in &self.steps {
+            acc = if step.left {
+                hash_branch(&step.sibling, &acc)
+            } else {
+                hash_branch(&acc, &step.sibling)
+            };
+        }
+        &acc == root
+    }
+}
+
+/// Signed integrity manifest emitted alongside a checkpoint: a Merkle root
+/// over every SST file plus enough per-leaf metadata to regenerate proofs
+/// and to map a failed proof back to a concrete file.
+#[derive(Debug, Clone)]
+pub struct CheckpointManifest {
+    pub root: MerkleHash,
+    pub leaves: Vec<ManifestLeaf>,
+    /// Signature over `root`, produced by whatever key the deployment uses
+    /// to authenticate checkpoints; left for the caller to fill in.
+    pub signature: Vec<u8>,
+}
+
+impl CheckpointManifest {
+    /// Builds a manifest incrementally: a leaf is appended for every entry
+    /// of `leaves_in_order` (already sorted per the per-level `BTreeMap`
+    /// iteration order), maintaining the Merkle tree as a vector of layers.
+    pub fn build(leaves_in_order: Vec<(ManifestLeaf, Vec<u8>)>) -> CheckpointManifest {
+        if leaves_in_order.is_empty() {
+            return CheckpointManifest {
+                root: ZERO_HASH,
+                leaves: vec![],
+                signature: vec![],
+            };
+        }
+
+        let mut builder = MerkleTreeBuilder::new();
+        let mut leaves = Vec::with_capacity(leaves_in_order.len());
+        for (mut leaf, contents) in leaves_in_order {
+            leaf.hash = hash_leaf(&contents);
+            builder.push(leaf.hash);
+            leaves.push(leaf);
+        }
+
+        CheckpointManifest {
+            root: builder.root(),
+            leaves,
+            signature: vec![],
+        }
+    }
+
+    /// Generates an inclusion proof for the leaf identified by `file_name`.
+    pub fn generate_proof(&self, file_name: &str) -> Option<MerkleProof> {
+        let (index, leaf) = self
+            .leaves
+            .iter()
+            .enumerate()
+            .find(|(_, l)| l.file_name == file_name)?;
+
+        let hashes: Vec<MerkleHash> = self.leaves.iter().map(|l| l.hash).collect();
+        let steps = MerkleTreeBuilder::proof_for(&hashes, index);
+        Some(MerkleProof {
+            leaf: leaf.clone(),
+            steps,
+        })
+    }
+}
+
+/// An incremental binary Merkle tree maintained as a vector of layers: layer
+/// 0 holds the leaves in insertion order, and each higher layer is derived
+/// from the one below it by combining adjacent pairs, promoting a lone
+/// trailing node unchanged instead of hashing it with itself. `push`
+/// appends a leaf to layer 0; `root` (re-)derives every higher layer from
+/// it on demand, which keeps the single source of truth in one place and
+/// avoids the layers drifting out of sync with each other.
+struct MerkleTreeBuilder {
+    layers: Vec<Vec<MerkleHash>>,
+}
+
+impl MerkleTreeBuilder {
+    fn new() -> Self {
+        MerkleTreeBuilder { layers: vec![vec![]] }
+    }
+
+    fn push(&mut self, leaf: MerkleHash) {
+        self.layers[0].push(leaf);
+    }
+
+    /// Folds layer 0 all the way up to a single root.
+    fn root(&self) -> MerkleHash {
+        Self::fold_layer(self.layers[0].clone())
+    }
+
+    fn fold_layer(mut nodes: Vec<MerkleHash>) -> MerkleHash {
+        while nodes.len() > 1 {
+            let mut next = Vec::with_capacity(nodes.len().div_ceil(2));
+            let mut it = nodes.chunks(2);
+            for pair in &mut it {
+                if pair.len() == 2 {
+                    next.push(hash_branch(&pair[0], &pair[1]));
+                } else {
+                    next.push(pair[0]);
+                }
+            }
+            nodes = next;
+        }
+        nodes[0]
+    }
+
+    /// Recomputes, from scratch, the sibling path for `index` within a flat
+    /// slice of leaf hashes. Used by `CheckpointManifest::generate_proof`
+    /// since proofs are generated lazily rather than kept live in the
+    /// builder.
+    fn proof_for(leaves: &[MerkleHash], mut index: usize) -> Vec<ProofStep> {
+        let mut steps = vec![];
+        let mut level: Vec<MerkleHash> = leaves.to_vec();
+        while level.len() > 1 {
+            let is_right = index % 2 == 1;
+            let pair_index = if is_right { index - 1 } else { index + 1 };
+            if pair_index < level.len() {
+                steps.push(ProofStep {
+                    sibling: level[pair_index],
+                    left: is_right,
+                });
+            }
+            // Fold this level the same way `fold_layer` does, to compute the
+            // next level the sibling path continues into.
+            let mut next = Vec::with_capacity(level.len().div_ceil(2));
+            let mut i = 0;
+            while i < level.len() {
+                if i + 1 < level.len() {
+                    next.push(hash_branch(&level[i], &level[i + 1]));
+                } else {
+                    next.push(level[i]);
+                }
+                i += 2;
+            }
+            index /= 2;
+            level = next;
+        }
+        steps
+    }
+}
+
 impl Debug for ColumnFamilyMetadata {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut binding = f.debug_struct("ColumnFamilyMetadata");
@@ -0,0 +1,225 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A shared memory budget for the batch hash aggregation executors.
+//!
+//! `BatchFastHashAggregationExecutor` and `BatchSlowHashAggregationExecutor`
+//! keep one hash table entry per distinct group key, so a high-cardinality
+//! `GROUP BY` can grow without bound. Each aggregator registers a
+//! [`MemoryConsumer`] against a process-wide [`MemoryManager`] and asks it
+//! before growing its hash table any further; when the shared budget is
+//! exhausted the request is denied rather than granted, and the aggregator
+//! is expected to partition its accumulator state to a temporary file,
+//! release its reservation, and keep consuming input from a now-empty
+//! table. This mirrors the consumer/grant/spill-first flow of DataFusion's
+//! memory manager, recast onto TiKV's batch executors.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicI64, Ordering},
+};
+
+/// Process-wide cap on memory used by hash aggregation hash tables across
+/// concurrently running coprocessor requests. All [`MemoryConsumer`]s
+/// created from the same `MemoryManager` compete for the same budget.
+pub struct MemoryManager {
+    limit: i64,
+    used: AtomicI64,
+}
+
+impl MemoryManager {
+    pub fn new(limit_bytes: u64) -> Arc<Self> {
+        Arc::new(Self {
+            // `limit_bytes` can exceed i64::MAX (e.g. `unbounded()` passes
+            // u64::MAX); clamp instead of truncating via `as i64`, which
+            // would wrap around to a negative limit and deny every grow.
+            limit: limit_bytes.min(i64::MAX as u64) as i64,
+            used: AtomicI64::new(0),
+        })
+    }
+
+    /// A manager with no effective cap, for call sites that never want
+    /// hash aggregation to spill.
+    pub fn unbounded() -> Arc<Self> {
+        Self::new(u64::MAX)
+    }
+
+    /// Registers a new consumer against this manager's shared budget.
+    pub fn new_consumer(self: &Arc<Self>, name: impl Into<String>) -> MemoryConsumer {
+        MemoryConsumer {
+            manager: Arc::clone(self),
+            name: name.into(),
+            reserved: AtomicI64::new(0),
+        }
+    }
+
+    /// Attempts to change total reserved usage by `delta` bytes. Negative
+    /// deltas (releasing memory) always succeed; positive deltas
+    /// (growing) are denied once they would push `used` past `limit`.
+    fn try_reserve(&self, delta: i64) -> bool {
+        if delta <= 0 {
+            self.used.fetch_add(delta, Ordering::SeqCst);
+            return true;
+        }
+        let mut current = self.used.load(Ordering::SeqCst);
+        loop {
+            if current.saturating_add(delta) > self.limit {
+                return false;
+            }
+            match self.used.compare_exchange_weak(
+                current,
+                current + delta,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return true,
+                Err(observed) => current = observed,
+            }
+        }
+    }
+}
+
+/// One hash aggregator's handle onto a shared [`MemoryManager`] budget.
+/// Tracks what this particular consumer currently holds, so it can
+/// release everything it owns (typically right after spilling) without
+/// the manager needing to know the breakdown of any one consumer's
+/// state.
+pub struct MemoryConsumer {
+    manager: Arc<MemoryManager>,
+    name: String,
+    reserved: AtomicI64,
+}
+
+impl MemoryConsumer {
+    /// Asks to grow (or shrink) this consumer's reservation to
+    /// `new_total_bytes`. Returns `true` if granted, meaning the
+    /// aggregator may keep growing its hash table, or `false` if the
+    /// shared budget is exhausted, meaning the aggregator must spill its
+    /// current accumulator state to disk and call [`Self::release`]
+    /// before it can grow again.
+    pub fn try_grow_to(&self, new_total_bytes: u64) -> bool {
+        let new_total = new_total_bytes as i64;
+        let current = self.reserved.load(Ordering::SeqCst);
+        let delta = new_total - current;
+        if delta <= 0 {
+            self.reserved.store(new_total, Ordering::SeqCst);
+            self.manager.try_reserve(delta);
+            return true;
+        }
+        if self.manager.try_reserve(delta) {
+            self.reserved.store(new_total, Ordering::SeqCst);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Grows this consumer's reservation by `delta_bytes` on top of
+    /// whatever it already holds. Returns `false`, leaving the
+    /// reservation unchanged, if doing so would exceed the shared
+    /// budget.
+    pub fn try_grow_by(&self, delta_bytes: u64) -> bool {
+        let current = self.reserved.load(Ordering::SeqCst).max(0) as u64;
+        self.try_grow_to(current.saturating_add(delta_bytes))
+    }
+
+    /// Releases this consumer's entire current reservation, typically
+    /// called right after the consumer has spilled the hash table it
+    /// was backing to a temporary file.
+    pub fn release(&self) {
+        let current = self.reserved.swap(0, Ordering::SeqCst);
+        if current != 0 {
+            self.manager.try_reserve(-current);
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for MemoryConsumer {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_grow_to_grants_until_limit_then_denies() {
+        let manager = MemoryManager::new(100);
+        let consumer = manager.new_consumer("c");
+
+        assert!(consumer.try_grow_to(60));
+        assert!(consumer.try_grow_to(100));
+        // Growing past the shared limit is denied and leaves the
+        // reservation exactly where it was before the attempt.
+        assert!(!consumer.try_grow_to(101));
+        assert_eq!(manager.used.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn test_try_grow_to_shrinking_always_succeeds() {
+        let manager = MemoryManager::new(100);
+        let consumer = manager.new_consumer("c");
+        assert!(consumer.try_grow_to(80));
+
+        assert!(consumer.try_grow_to(20));
+        assert_eq!(manager.used.load(Ordering::SeqCst), 20);
+    }
+
+    #[test]
+    fn test_try_grow_by_accumulates_on_top_of_current_reservation() {
+        let manager = MemoryManager::new(100);
+        let consumer = manager.new_consumer("c");
+
+        assert!(consumer.try_grow_by(30));
+        assert!(consumer.try_grow_by(40));
+        assert_eq!(manager.used.load(Ordering::SeqCst), 70);
+
+        // 70 + 40 would exceed the 100-byte budget.
+        assert!(!consumer.try_grow_by(40));
+        assert_eq!(manager.used.load(Ordering::SeqCst), 70);
+    }
+
+    #[test]
+    fn test_release_returns_the_whole_reservation_to_the_shared_budget() {
+        let manager = MemoryManager::new(100);
+        let consumer = manager.new_consumer("c");
+        assert!(consumer.try_grow_by(90));
+
+        consumer.release();
+
+        assert_eq!(manager.used.load(Ordering::SeqCst), 0);
+        // The budget is available again for a fresh grow.
+        assert!(consumer.try_grow_by(90));
+    }
+
+    /// The budget is shared: one consumer exhausting it must deny another
+    /// consumer's grow, mirroring a hash aggregator and the coprocessor
+    /// response buffer competing for the same `MemoryManager`.
+    #[test]
+    fn test_shared_budget_exhaustion_blocks_other_consumers() {
+        let manager = MemoryManager::new(100);
+        let hash_agg = manager.new_consumer("hash-agg");
+        let response_buffer = manager.new_consumer("coprocessor-response");
+
+        assert!(hash_agg.try_grow_to(100));
+        assert!(!response_buffer.try_grow_by(1));
+
+        // Once the first consumer spills and releases, the budget is
+        // available to the other consumer again.
+        hash_agg.release();
+        assert!(response_buffer.try_grow_by(1));
+    }
+
+    #[test]
+    fn test_unbounded_manager_never_denies_a_grow() {
+        let manager = MemoryManager::unbounded();
+        let consumer = manager.new_consumer("c");
+
+        assert!(consumer.try_grow_to(u64::MAX / 2));
+    }
+}
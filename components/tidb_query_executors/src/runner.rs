@@ -1,6 +1,13 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::{convert::TryFrom, sync::Arc};
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
 
 use api_version::KvFormat;
 use fail::fail_point;
@@ -9,6 +16,7 @@ use kvproto::coprocessor::KeyRange;
 use protobuf::Message;
 use tidb_query_common::{
     Result,
+    error::Error,
     execute_stats::ExecSummary,
     metrics::*,
     storage::{IntervalRange, Storage},
@@ -23,12 +31,13 @@ use tikv_util::{
     quota_limiter::QuotaLimiter,
 };
 use tipb::{
-    self, Chunk, DagRequest, EncodeType, ExecType, ExecutorExecutionSummary, FieldType,
+    self, Chunk, DagRequest, EncodeType, ExecType, ExecutorExecutionSummary, Expr, FieldType,
     SelectResponse, StreamResponse,
 };
 
 use super::{
     interface::{BatchExecIsDrain, BatchExecutor, ExecuteStats},
+    memory_manager::{MemoryConsumer, MemoryManager},
     *,
 };
 
@@ -44,6 +53,50 @@ pub use tidb_query_expr::types::BATCH_MAX_SIZE;
 // TODO: Maybe there can be some better strategy. Needs benchmarks and tunes.
 const BATCH_GROW_FACTOR: usize = 2;
 
+/// Runner-observed timing and throughput for one request, covering the time
+/// spent awaiting `next_batch` plus encoding its result and the rows/bytes
+/// that produced. This is necessarily scoped to the runner's own fetch+encode
+/// loop around the outermost executor rather than broken down per inner
+/// executor: each inner executor's own iteration/row counters already live
+/// in `ExecSummary`, collected separately via `collect_exec_stats`, but the
+/// executors themselves don't carry their own `BaselineMetrics`-style
+/// wall-clock timers in this tree. Mirrors the elapsed-time and row/byte
+/// counters DataFusion attaches to every operator via its own
+/// `BaselineMetrics`.
+#[derive(Default)]
+struct BaselineMetrics {
+    elapsed_compute_ns: u64,
+    output_rows: u64,
+    output_bytes: u64,
+}
+
+impl BaselineMetrics {
+    fn record_elapsed(&mut self, elapsed: std::time::Duration) {
+        self.elapsed_compute_ns += elapsed.as_nanos() as u64;
+    }
+
+    fn record_output(&mut self, rows: usize, bytes: usize) {
+        self.output_rows += rows as u64;
+        self.output_bytes += bytes as u64;
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Sets the `BaselineMetrics` fields on the outermost executor's summary
+/// (the last entry, following `summary_per_executor`'s innermost-to-outermost
+/// order), since that's the entry whose fetch+encode work the runner's own
+/// timers actually measured.
+fn attach_baseline_metrics(summaries: &mut [ExecutorExecutionSummary], metrics: &BaselineMetrics) {
+    if let Some(outermost) = summaries.last_mut() {
+        outermost.set_elapsed_compute_ns(metrics.elapsed_compute_ns);
+        outermost.set_output_rows(metrics.output_rows);
+        outermost.set_output_bytes(metrics.output_bytes);
+    }
+}
+
 pub struct BatchExecutorsRunner<SS> {
     /// The deadline of this handler. For each check point (e.g. each iteration)
     /// we need to check whether or not the deadline is exceeded and break
@@ -79,6 +132,33 @@ pub struct BatchExecutorsRunner<SS> {
     paging_size: Option<u64>,
 
     quota_limiter: Arc<QuotaLimiter>,
+
+    /// Shared cap on hash aggregation hash table memory, consulted by the
+    /// fast/slow hash aggregation executors before growing further.
+    memory_manager: Arc<MemoryManager>,
+
+    /// This request's reservation against `memory_manager` for
+    /// in-flight response buffer bytes (`Chunk::rows_data`), so a wide
+    /// scan can't grow its encoded output without bound.
+    response_memory: MemoryConsumer,
+
+    /// Rows that have been encoded but not yet handed to a `StreamResponse`,
+    /// kept individually so [`Self::handle_streaming_request`] can
+    /// repartition them into chunks of exactly
+    /// `config.stream_num_rows_per_chunk` rows instead of whatever
+    /// `next_batch` happened to produce. Only used when that setting is
+    /// non-zero; carried across calls, one call at a time, like a parquet
+    /// writer buffering rows until it has a full row group to flush.
+    stream_pending_rows: VecDeque<Vec<u8>>,
+
+    /// Whether the underlying executor has reported drain, so a later
+    /// streaming call that's still flushing `stream_pending_rows` knows not
+    /// to poll `next_batch` again.
+    stream_source_drained: bool,
+
+    /// Wall-clock time and throughput the runner itself observed fetching
+    /// and encoding batches since the last response was produced.
+    baseline_metrics: BaselineMetrics,
 }
 
 // We assign a dummy type `()` so that we can omit the type when calling
@@ -152,7 +232,9 @@ impl BatchExecutorsRunner<()> {
                     return Err(other_err!("PartitionTableScan executor not implemented"));
                 }
                 ExecType::TypeSort => {
-                    return Err(other_err!("Sort executor not implemented"));
+                    let descriptor = ed.get_sort();
+                    BatchSortExecutor::check_supported(descriptor)
+                        .map_err(|e| other_err!("BatchSortExecutor: {}", e))?;
                 }
                 ExecType::TypeWindow => {
                     return Err(other_err!("Window executor not implemented"));
@@ -175,15 +257,61 @@ fn is_arrow_encodable<'a>(mut schema: impl Iterator<Item = &'a FieldType>) -> bo
     schema.all(|schema| EvalType::try_from(schema.as_accessor().tp()).is_ok())
 }
 
+/// Returns the row count that can be pushed down as a *soft* limit into a
+/// pure GROUP-BY (DISTINCT) aggregation, i.e. `group_by` is non-empty and
+/// `agg_func` is empty: once that many distinct groups have been seen,
+/// the aggregator may stop pulling from its child and report drain
+/// early. Applies when `next` — peeked, not consumed — is a `Limit` with
+/// no `partition_by`, or a `TopN` with no `partition_by` whose order keys
+/// are a prefix of `group_by`.
+fn distinct_soft_limit(next: Option<&tipb::Executor>, group_by: &[Expr]) -> Option<u64> {
+    let next = next?;
+    match next.get_tp() {
+        ExecType::TypeLimit => {
+            let d = next.get_limit();
+            d.get_partition_by().is_empty().then(|| d.get_limit())
+        }
+        ExecType::TypeTopN => {
+            let d = next.get_top_n();
+            let order_by = d.get_order_by();
+            if !d.get_partition_by().is_empty() || order_by.len() > group_by.len() {
+                return None;
+            }
+            let is_prefix = order_by
+                .iter()
+                .zip(group_by)
+                .all(|(item, g)| item.get_expr() == g);
+            is_prefix.then(|| d.get_limit())
+        }
+        _ => None,
+    }
+}
+
+/// Returns the fetch limit a `Sort` may fuse with, if `next` — peeked, not
+/// consumed — is a plain (non-partitioned) `Limit` immediately following
+/// it. A `Sort` this is `Some` for can be built directly as a bounded
+/// `BatchTopNExecutor` instead of paying for a full external sort whose
+/// output is then truncated.
+fn sort_fetch_limit(next: Option<&tipb::Executor>) -> Option<usize> {
+    let next = next?;
+    if next.get_tp() == ExecType::TypeLimit && next.get_limit().get_partition_by().is_empty() {
+        Some(next.get_limit().get_limit() as usize)
+    } else {
+        None
+    }
+}
+
 #[allow(clippy::explicit_counter_loop)]
 pub fn build_executors<S: Storage + 'static, F: KvFormat>(
     executor_descriptors: Vec<tipb::Executor>,
     storage: S,
     ranges: Vec<KeyRange>,
     config: Arc<EvalConfig>,
+    memory_manager: Arc<MemoryManager>,
     is_scanned_range_aware: bool,
+    deadline: Deadline,
 ) -> Result<Box<dyn BatchExecutor<StorageStats = S::Statistics>>> {
-    let mut executor_descriptors = executor_descriptors.into_iter();
+    let mut executor_descriptors = executor_descriptors.into_iter().peekable();
     let mut first_ed = executor_descriptors
         .next()
         .ok_or_else(|| other_err!("No executors"))?;
@@ -192,6 +320,11 @@ pub fn build_executors<S: Storage + 'static, F: KvFormat>(
     // Limit executor use this flag to check if its src is table/index scan.
     // Performance enhancement for plan like: limit 1 -> table/index scan.
     let mut is_src_scan_executor = true;
+    // Set when a `Sort` was fused with the `Limit` immediately following it
+    // into a single bounded `BatchTopNExecutor`; the next loop iteration
+    // (that same `Limit` descriptor) then contributes no executor of its
+    // own, since the fused TopN already bounds the output.
+    let mut sort_limit_fused = false;
 
     let mut executor: Box<dyn BatchExecutor<StorageStats = S::Statistics>> = match first_ed.get_tp()
     {
@@ -288,6 +421,13 @@ pub fn build_executors<S: Storage + 'static, F: KvFormat>(
                 )
             }
             ExecType::TypeAggregation => {
+                let soft_limit = (config.enable_distinct_limit_pushdown
+                    && ed.get_aggregation().get_agg_func().is_empty())
+                .then(|| {
+                    distinct_soft_limit(executor_descriptors.peek(), ed.get_aggregation().get_group_by())
+                })
+                .flatten();
+
                 if BatchFastHashAggregationExecutor::check_supported(ed.get_aggregation()).is_ok() {
                     EXECUTOR_COUNT_METRICS.batch_fast_hash_aggr.inc();
 
@@ -297,6 +437,8 @@ pub fn build_executors<S: Storage + 'static, F: KvFormat>(
                             executor,
                             ed.mut_aggregation().take_group_by().into(),
                             ed.mut_aggregation().take_agg_func().into(),
+                            memory_manager.clone(),
+                            soft_limit,
                         )?
                         .collect_summary(summary_slot_index),
                     )
@@ -309,12 +451,21 @@ pub fn build_executors<S: Storage + 'static, F: KvFormat>(
                             executor,
                             ed.mut_aggregation().take_group_by().into(),
                             ed.mut_aggregation().take_agg_func().into(),
+                            memory_manager.clone(),
+                            soft_limit,
                         )?
                         .collect_summary(summary_slot_index),
                     )
                 }
             }
             ExecType::TypeStreamAgg => {
+                let soft_limit = (config.enable_distinct_limit_pushdown
+                    && ed.get_aggregation().get_agg_func().is_empty())
+                .then(|| {
+                    distinct_soft_limit(executor_descriptors.peek(), ed.get_aggregation().get_group_by())
+                })
+                .flatten();
+
                 EXECUTOR_COUNT_METRICS.batch_stream_aggr.inc();
 
                 Box::new(
@@ -323,10 +474,15 @@ pub fn build_executors<S: Storage + 'static, F: KvFormat>(
                         executor,
                         ed.mut_aggregation().take_group_by().into(),
                         ed.mut_aggregation().take_agg_func().into(),
+                        soft_limit,
                     )?
                     .collect_summary(summary_slot_index),
                 )
             }
+            ExecType::TypeLimit if sort_limit_fused => {
+                sort_limit_fused = false;
+                executor
+            }
             ExecType::TypeLimit => {
                 EXECUTOR_COUNT_METRICS.batch_limit.inc();
 
@@ -406,6 +562,53 @@ pub fn build_executors<S: Storage + 'static, F: KvFormat>(
                     )
                 }
             }
+            ExecType::TypeSort => {
+                let mut d = ed.take_sort();
+                let order_bys = d.get_by_items().len();
+                let mut order_exprs_def = Vec::with_capacity(order_bys);
+                let mut order_is_desc = Vec::with_capacity(order_bys);
+                for mut item in d.take_by_items().into_iter() {
+                    order_exprs_def.push(item.take_expr());
+                    order_is_desc.push(item.get_desc());
+                }
+
+                // A `Sort` immediately followed by a plain `Limit` is a
+                // Top-K: build the bounded-heap `BatchTopNExecutor`
+                // directly instead of paying for a full external sort and
+                // then truncating its output. The `Limit` descriptor is
+                // still consumed as its own loop iteration below, but
+                // contributes no executor of its own once fused.
+                let fetch_limit = sort_fetch_limit(executor_descriptors.peek());
+
+                if let Some(limit) = fetch_limit {
+                    EXECUTOR_COUNT_METRICS.batch_top_n.inc();
+                    sort_limit_fused = true;
+
+                    Box::new(
+                        BatchTopNExecutor::new(
+                            config.clone(),
+                            executor,
+                            order_exprs_def,
+                            order_is_desc,
+                            limit,
+                        )?
+                        .collect_summary(summary_slot_index),
+                    )
+                } else {
+                    EXECUTOR_COUNT_METRICS.batch_sort.inc();
+
+                    Box::new(
+                        BatchSortExecutor::new(
+                            config.clone(),
+                            executor,
+                            order_exprs_def,
+                            order_is_desc,
+                            deadline,
+                        )?
+                        .collect_summary(summary_slot_index),
+                    )
+                }
+            }
             _ => {
                 return Err(other_err!(
                     "Unexpected non-first executor {:?}",
@@ -429,6 +632,7 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
         is_streaming: bool,
         paging_size: Option<u64>,
         quota_limiter: Arc<QuotaLimiter>,
+        memory_manager: Arc<MemoryManager>,
     ) -> Result<Self> {
         let executors_len = req.get_executors().len();
         let collect_exec_summary = req.get_collect_execution_summaries();
@@ -441,9 +645,11 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
             storage,
             ranges,
             config.clone(),
+            memory_manager.clone(),
             is_streaming || paging_size.is_some(), /* For streaming and paging request,
                                                     * executors will continue scan from range
                                                     * end where last scan is finished */
+            deadline,
         )?;
 
         // Check output offsets
@@ -470,6 +676,7 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
         };
 
         let exec_stats = ExecuteStats::new(executors_len);
+        let response_memory = memory_manager.new_consumer("coprocessor-response");
 
         Ok(Self {
             deadline,
@@ -482,6 +689,11 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
             encode_type,
             paging_size,
             quota_limiter,
+            memory_manager,
+            response_memory,
+            stream_pending_rows: VecDeque::new(),
+            stream_source_drained: false,
+            baseline_metrics: BaselineMetrics::default(),
         })
     }
 
@@ -498,6 +710,10 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
     /// ranges. e.g.: [(k1 -> k2), (k4 -> k5)] may got response (k1, k2, k4)
     /// with IntervalRange like (k1, k4).
     pub async fn handle_request(&mut self) -> Result<(SelectResponse, Option<IntervalRange>)> {
+        if self.config.batch_prefetch_depth > 0 {
+            return self.handle_request_pipelined().await;
+        }
+
         let mut chunks = vec![];
         let mut batch_size = Self::batch_initial_size();
         let mut warnings = self.config.new_eval_warnings();
@@ -520,9 +736,13 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
                 sample.add_cpu_time(cpu_time);
                 res?
             };
-            if chunk.has_rows_data() {
-                sample.add_read_bytes(chunk.get_rows_data().len());
-            }
+            let chunk_bytes = if chunk.has_rows_data() {
+                let n = chunk.get_rows_data().len();
+                sample.add_read_bytes(n);
+                n
+            } else {
+                0
+            };
 
             let quota_delay = self.quota_limiter.consume_sample(sample, true).await;
             if !quota_delay.is_zero() {
@@ -561,7 +781,199 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
                 );
 
                 if self.collect_exec_summary {
-                    let summaries = self
+                    let mut summaries = self
+                        .exec_stats
+                        .summary_per_executor
+                        .iter()
+                        .map(|summary| {
+                            let mut ret = ExecutorExecutionSummary::default();
+                            ret.set_num_iterations(summary.num_iterations as u64);
+                            ret.set_num_produced_rows(summary.num_produced_rows as u64);
+                            ret.set_time_processed_ns(summary.time_processed_ns as u64);
+                            ret
+                        })
+                        .collect::<Vec<_>>();
+                    attach_baseline_metrics(&mut summaries, &self.baseline_metrics);
+                    sel_resp.set_execution_summaries(summaries.into());
+                }
+
+                sel_resp.set_warnings(warnings.warnings.into());
+                sel_resp.set_warning_count(warnings.warning_cnt as i64);
+                return Ok((sel_resp, range));
+            }
+
+            // Grow batch size, targeting a roughly stable encoded chunk size
+            // rather than blindly doubling, using the previous chunk's
+            // observed bytes-per-row.
+            grow_batch_size(
+                &mut batch_size,
+                chunk_bytes,
+                record_len,
+                self.config.target_chunk_bytes,
+            );
+        }
+    }
+
+    /// Same as [`Self::handle_request`], but keeps the `next_batch` future
+    /// for the following chunk already polled once before encoding the
+    /// chunk just received, so a storage read that blocks on RocksDB or
+    /// remote IO overlaps with this chunk's CPU-bound encode instead of the
+    /// two running strictly back to back.
+    ///
+    /// `BatchExecutor::next_batch` takes `&mut self` on the executor, so at
+    /// most one fetch can ever be outstanding no matter how deep a queue we
+    /// wanted: `batch_prefetch_depth` only toggles whether that one fetch is
+    /// kicked off early, it does not let us hold several in flight.
+    async fn handle_request_pipelined(
+        &mut self,
+    ) -> Result<(SelectResponse, Option<IntervalRange>)> {
+        let mut chunks = vec![];
+        let mut batch_size = Self::batch_initial_size();
+        let mut warnings = self.config.new_eval_warnings();
+        let mut ctx = EvalContext::new(self.config.clone());
+        let mut record_all = 0;
+
+        self.deadline.check()?;
+        let mut next_result = Some(self.out_most_executor.next_batch(batch_size).await);
+
+        loop {
+            let mut result = next_result.take().expect("chunk already prefetched");
+            let is_drained = result.is_drained?;
+            let drained = is_drained.stop();
+            // Snapshotted before the following fetch may start borrowing
+            // `self.out_most_executor` again, since the encode below can't
+            // call `self.out_most_executor.schema()` while that borrow is
+            // outstanding.
+            let schema = self.out_most_executor.schema().to_vec();
+
+            // Kick the following fetch off before encoding the chunk we just
+            // got, so its IO wait (if any) overlaps with the encode below.
+            let mut pending = if drained {
+                None
+            } else {
+                self.deadline.check()?;
+                let mut fut = Box::pin(self.out_most_executor.next_batch(batch_size));
+                let waker = futures::task::noop_waker();
+                let mut cx = Context::from_waker(&waker);
+                match fut.as_mut().poll(&mut cx) {
+                    Poll::Ready(r) => {
+                        next_result = Some(r);
+                        None
+                    }
+                    Poll::Pending => Some(fut),
+                }
+            };
+
+            let mut chunk = Chunk::default();
+            let mut sample = self.quota_limiter.new_sample(true);
+            // Fetch wait isn't timed separately here: by design it overlaps
+            // with the encode below instead of being awaited serially, so
+            // there's no standalone "time spent fetching" to attribute.
+            let encode_start = Instant::now();
+            let (cpu_time, record_len) = sample
+                .observe_cpu_async(async {
+                    let mut record_len = 0;
+                    if !result.logical_rows.is_empty() {
+                        assert_eq!(result.physical_columns.columns_len(), schema.len());
+                        let data = chunk.mut_rows_data();
+                        if self.encode_type == EncodeType::TypeDefault {
+                            let required = result
+                                .physical_columns
+                                .maximum_encoded_size(&result.logical_rows, &self.output_offsets);
+                            if !self.response_memory.try_grow_by(required as u64) {
+                                return Err(Error::ResourceExhausted(format!(
+                                    "coprocessor response buffer exceeds the memory budget for {}",
+                                    self.response_memory.name()
+                                )));
+                            }
+                            data.reserve(required);
+                            result.physical_columns.encode(
+                                &result.logical_rows,
+                                &self.output_offsets,
+                                &schema,
+                                data,
+                                &mut ctx,
+                            )?;
+                        } else {
+                            let required = result.physical_columns.maximum_encoded_size_chunk(
+                                &result.logical_rows,
+                                &self.output_offsets,
+                            );
+                            if !self.response_memory.try_grow_by(required as u64) {
+                                return Err(Error::ResourceExhausted(format!(
+                                    "coprocessor response buffer exceeds the memory budget for {}",
+                                    self.response_memory.name()
+                                )));
+                            }
+                            data.reserve(required);
+                            result.physical_columns.encode_chunk(
+                                &result.logical_rows,
+                                &self.output_offsets,
+                                &schema,
+                                data,
+                                &mut ctx,
+                            )?;
+                        }
+                        record_len = result.logical_rows.len();
+                    }
+                    Ok(record_len)
+                })
+                .await;
+            sample.add_cpu_time(cpu_time);
+            let record_len = record_len?;
+            self.baseline_metrics.record_elapsed(encode_start.elapsed());
+            self.baseline_metrics
+                .record_output(record_len, chunk.get_rows_data().len());
+            warnings.merge(&mut result.warnings);
+
+            let chunk_bytes = if chunk.has_rows_data() {
+                let n = chunk.get_rows_data().len();
+                sample.add_read_bytes(n);
+                n
+            } else {
+                0
+            };
+
+            let quota_delay = self.quota_limiter.consume_sample(sample, true).await;
+            if !quota_delay.is_zero() {
+                NON_TXN_COMMAND_THROTTLE_TIME_COUNTER_VEC_STATIC
+                    .get(ThrottleType::dag)
+                    .inc_by(quota_delay.as_micros() as u64);
+            }
+
+            if record_len > 0 {
+                chunks.push(chunk);
+                record_all += record_len;
+            }
+
+            if let Some(fut) = pending.take() {
+                next_result = Some(fut.await);
+            }
+
+            if drained || self.paging_size.is_some_and(|p| record_all >= p as usize) {
+                self.out_most_executor
+                    .collect_exec_stats(&mut self.exec_stats);
+                let range = if is_drained == BatchExecIsDrain::Drain {
+                    None
+                } else {
+                    self.paging_size
+                        .map(|_| self.out_most_executor.take_scanned_range())
+                };
+
+                let mut sel_resp = SelectResponse::default();
+                sel_resp.set_chunks(chunks.into());
+                sel_resp.set_encode_type(self.encode_type);
+
+                sel_resp.set_output_counts(
+                    self.exec_stats
+                        .scanned_rows_per_range
+                        .iter()
+                        .map(|v| *v as i64)
+                        .collect(),
+                );
+
+                if self.collect_exec_summary {
+                    let mut summaries = self
                         .exec_stats
                         .summary_per_executor
                         .iter()
@@ -573,6 +985,7 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
                             ret
                         })
                         .collect::<Vec<_>>();
+                    attach_baseline_metrics(&mut summaries, &self.baseline_metrics);
                     sel_resp.set_execution_summaries(summaries.into());
                 }
 
@@ -581,14 +994,24 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
                 return Ok((sel_resp, range));
             }
 
-            // Grow batch size
-            grow_batch_size(&mut batch_size);
+            grow_batch_size(
+                &mut batch_size,
+                chunk_bytes,
+                record_len,
+                self.config.target_chunk_bytes,
+            );
         }
     }
 
     pub async fn handle_streaming_request(
         &mut self,
     ) -> Result<(Option<(StreamResponse, IntervalRange)>, bool)> {
+        if self.config.stream_num_rows_per_chunk > 0 {
+            return self
+                .handle_streaming_request_repartitioned(self.config.stream_num_rows_per_chunk)
+                .await;
+        }
+
         let mut warnings = self.config.new_eval_warnings();
 
         let (mut record_len, mut is_drained) = (0, false);
@@ -625,6 +1048,69 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
         Ok((None, true))
     }
 
+    /// Like [`Self::handle_streaming_request`], but every chunk it returns
+    /// (other than possibly the very last one) holds exactly
+    /// `num_rows_per_chunk` logical rows, regardless of how `next_batch`
+    /// happened to batch internally. Rows are encoded individually and kept
+    /// in `self.stream_pending_rows` until a full chunk's worth have
+    /// accumulated, with any overflow carried forward to the next call.
+    async fn handle_streaming_request_repartitioned(
+        &mut self,
+        num_rows_per_chunk: usize,
+    ) -> Result<(Option<(StreamResponse, IntervalRange)>, bool)> {
+        let mut warnings = self.config.new_eval_warnings();
+        let mut ctx = EvalContext::new(self.config.clone());
+        let batch_size = self.stream_row_limit.min(BATCH_MAX_SIZE);
+
+        while self.stream_pending_rows.len() < num_rows_per_chunk && !self.stream_source_drained {
+            self.deadline.check()?;
+            let fetch_start = Instant::now();
+            let mut result = self.out_most_executor.next_batch(batch_size).await;
+            self.baseline_metrics.record_elapsed(fetch_start.elapsed());
+            self.stream_source_drained = result.is_drained?.stop();
+
+            let encode_start = Instant::now();
+            if !result.logical_rows.is_empty() {
+                let schema = self.out_most_executor.schema();
+                for &row in &result.logical_rows {
+                    let mut row_data = Vec::new();
+                    result.physical_columns.encode(
+                        &[row],
+                        &self.output_offsets,
+                        schema,
+                        &mut row_data,
+                        &mut ctx,
+                    )?;
+                    self.baseline_metrics.record_output(1, row_data.len());
+                    self.stream_pending_rows.push_back(row_data);
+                }
+            }
+            self.baseline_metrics.record_elapsed(encode_start.elapsed());
+            warnings.merge(&mut result.warnings);
+        }
+
+        if self.stream_pending_rows.is_empty() && self.stream_source_drained {
+            return Ok((None, true));
+        }
+
+        let mut chunk = Chunk::default();
+        let mut record_len = 0;
+        while record_len < num_rows_per_chunk {
+            match self.stream_pending_rows.pop_front() {
+                Some(row_data) => {
+                    chunk.mut_rows_data().extend_from_slice(&row_data);
+                    record_len += 1;
+                }
+                None => break,
+            }
+        }
+
+        let is_drained = self.stream_source_drained && self.stream_pending_rows.is_empty();
+        let range = self.out_most_executor.take_scanned_range();
+        self.make_stream_response(chunk, warnings)
+            .map(|r| (Some((r, range)), is_drained))
+    }
+
     pub fn collect_storage_stats(&mut self, dest: &mut SS) {
         self.out_most_executor.collect_storage_stats(dest);
     }
@@ -652,10 +1138,13 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
 
         self.deadline.check()?;
 
+        let fetch_start = Instant::now();
         let mut result = self.out_most_executor.next_batch(batch_size).await;
+        self.baseline_metrics.record_elapsed(fetch_start.elapsed());
 
         let is_drained = result.is_drained?;
 
+        let encode_start = Instant::now();
         if !result.logical_rows.is_empty() {
             assert_eq!(
                 result.physical_columns.columns_len(),
@@ -666,11 +1155,16 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
                 // Although `schema()` can be deeply nested, it is ok since we process data in
                 // batch.
                 if is_streaming || self.encode_type == EncodeType::TypeDefault {
-                    data.reserve(
-                        result
-                            .physical_columns
-                            .maximum_encoded_size(&result.logical_rows, &self.output_offsets),
-                    );
+                    let required = result
+                        .physical_columns
+                        .maximum_encoded_size(&result.logical_rows, &self.output_offsets);
+                    if !self.response_memory.try_grow_by(required as u64) {
+                        return Err(Error::ResourceExhausted(format!(
+                            "coprocessor response buffer exceeds the memory budget for {}",
+                            self.response_memory.name()
+                        )));
+                    }
+                    data.reserve(required);
                     result.physical_columns.encode(
                         &result.logical_rows,
                         &self.output_offsets,
@@ -679,11 +1173,16 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
                         ctx,
                     )?;
                 } else {
-                    data.reserve(
-                        result
-                            .physical_columns
-                            .maximum_encoded_size_chunk(&result.logical_rows, &self.output_offsets),
-                    );
+                    let required = result
+                        .physical_columns
+                        .maximum_encoded_size_chunk(&result.logical_rows, &self.output_offsets);
+                    if !self.response_memory.try_grow_by(required as u64) {
+                        return Err(Error::ResourceExhausted(format!(
+                            "coprocessor response buffer exceeds the memory budget for {}",
+                            self.response_memory.name()
+                        )));
+                    }
+                    data.reserve(required);
                     result.physical_columns.encode_chunk(
                         &result.logical_rows,
                         &self.output_offsets,
@@ -695,6 +1194,9 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
             }
             record_len += result.logical_rows.len();
         }
+        self.baseline_metrics.record_elapsed(encode_start.elapsed());
+        self.baseline_metrics
+            .record_output(record_len, chunk.get_rows_data().len());
 
         warnings.merge(&mut result.warnings);
         Ok((is_drained, record_len))
@@ -710,6 +1212,7 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
 
         let mut s_resp = StreamResponse::default();
         s_resp.set_data(box_try!(chunk.write_to_bytes()));
+        self.response_memory.release();
 
         s_resp.set_output_counts(
             self.exec_stats
@@ -719,10 +1222,28 @@ impl<SS: 'static> BatchExecutorsRunner<SS> {
                 .collect(),
         );
 
+        if self.collect_exec_summary {
+            let mut summaries = self
+                .exec_stats
+                .summary_per_executor
+                .iter()
+                .map(|summary| {
+                    let mut ret = ExecutorExecutionSummary::default();
+                    ret.set_num_iterations(summary.num_iterations as u64);
+                    ret.set_num_produced_rows(summary.num_produced_rows as u64);
+                    ret.set_time_processed_ns(summary.time_processed_ns as u64);
+                    ret
+                })
+                .collect::<Vec<_>>();
+            attach_baseline_metrics(&mut summaries, &self.baseline_metrics);
+            s_resp.set_execution_summaries(summaries.into());
+        }
+
         s_resp.set_warnings(warnings.warnings.into());
         s_resp.set_warning_count(warnings.warning_cnt as i64);
 
         self.exec_stats.clear();
+        self.baseline_metrics.clear();
 
         Ok(s_resp)
     }
@@ -735,12 +1256,441 @@ fn batch_grow_factor() -> usize {
     BATCH_GROW_FACTOR
 }
 
+/// Picks the next batch size. The geometric row-count doubling is always
+/// computed first and acts as the upper bound on how fast a batch can grow
+/// in one step; when the previous chunk also gives us a bytes-per-row
+/// estimate, that doubled size is additionally capped so the *projected*
+/// encoded size of the next batch stays under `target_chunk_bytes`. Capping
+/// rather than replacing the geometric estimate means a narrow-row schema
+/// still converges to large batches over a few iterations instead of
+/// jumping straight to `BATCH_MAX_SIZE`, while a wide-row schema is kept
+/// from producing an oversized chunk on the very next call.
 #[inline]
-fn grow_batch_size(batch_size: &mut usize) {
-    if *batch_size < BATCH_MAX_SIZE {
-        *batch_size *= batch_grow_factor();
-        if *batch_size > BATCH_MAX_SIZE {
-            *batch_size = BATCH_MAX_SIZE
+fn grow_batch_size(
+    batch_size: &mut usize,
+    last_chunk_bytes: usize,
+    last_record_len: usize,
+    target_chunk_bytes: usize,
+) {
+    if *batch_size >= BATCH_MAX_SIZE {
+        return;
+    }
+    let geometric_next = *batch_size * batch_grow_factor();
+    let next = if last_record_len > 0 && last_chunk_bytes > 0 {
+        let avg_row_bytes = (last_chunk_bytes / last_record_len).max(1);
+        let byte_capped = (target_chunk_bytes / avg_row_bytes).max(1);
+        geometric_next.min(byte_capped)
+    } else {
+        geometric_next
+    };
+    *batch_size = next.clamp(1, BATCH_MAX_SIZE);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use tidb_query_datatype::{
+        FieldTypeTp,
+        codec::{
+            batch::{LazyBatchColumn, LazyBatchColumnVec},
+            datum::Datum,
+        },
+    };
+    use tikv_util::quota_limiter::QuotaLimiter;
+    use tipb::{Limit, TopN};
+
+    use super::*;
+
+    /// Feeds a fixed, canned sequence of [`BatchExecuteResult`]s to whatever
+    /// pulls from it, standing in for a real child executor (scan,
+    /// selection, ...) so the runner's own fetch/encode/repartition logic
+    /// can be exercised without a `Storage` implementation.
+    struct MockExecutor {
+        schema: Vec<FieldType>,
+        results: std::vec::IntoIter<BatchExecuteResult>,
+    }
+
+    impl MockExecutor {
+        fn new(schema: Vec<FieldType>, results: Vec<BatchExecuteResult>) -> Self {
+            Self {
+                schema,
+                results: results.into_iter(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl BatchExecutor for MockExecutor {
+        type StorageStats = ();
+
+        fn schema(&self) -> &[FieldType] {
+            &self.schema
+        }
+
+        async fn next_batch(&mut self, _scan_rows: usize) -> BatchExecuteResult {
+            self.results
+                .next()
+                .expect("MockExecutor ran out of canned batches")
+        }
+
+        fn collect_exec_stats(&mut self, _dest: &mut ExecuteStats) {}
+
+        fn collect_storage_stats(&mut self, _dest: &mut Self::StorageStats) {}
+
+        fn take_scanned_range(&mut self) -> IntervalRange {
+            IntervalRange::default()
+        }
+
+        fn can_be_cached(&self) -> bool {
+            false
+        }
+    }
+
+    fn int_schema() -> Vec<FieldType> {
+        let mut ft = FieldType::default();
+        ft.as_mut_accessor().set_tp(FieldTypeTp::LongLong);
+        vec![ft]
+    }
+
+    /// Builds a one-column, all-`i64` batch, as if it were read off a
+    /// single-column table.
+    fn int_batch(values: &[i64], drained: bool) -> BatchExecuteResult {
+        let mut column = LazyBatchColumn::decoded_with_capacity_and_tp(values.len(), EvalType::Int);
+        for v in values {
+            column.mut_decoded().push_datum(&Datum::I64(*v)).unwrap();
+        }
+        let mut columns = LazyBatchColumnVec::with_capacity(1);
+        columns.push(column);
+        BatchExecuteResult {
+            physical_columns: columns,
+            logical_rows: (0..values.len()).collect(),
+            warnings: EvalConfig::default().new_eval_warnings(),
+            is_drained: Ok(if drained {
+                BatchExecIsDrain::Drain
+            } else {
+                BatchExecIsDrain::Remain
+            }),
+        }
+    }
+
+    /// Constructs a `BatchExecutorsRunner` directly from its fields (rather
+    /// than via `from_request`, which needs a real `Storage`), wrapping
+    /// `executor` as the sole, outermost executor.
+    fn test_runner(
+        executor: MockExecutor,
+        config: Arc<EvalConfig>,
+        memory_manager: Arc<MemoryManager>,
+    ) -> BatchExecutorsRunner<()> {
+        let response_memory = memory_manager.new_consumer("coprocessor-response");
+        BatchExecutorsRunner {
+            deadline: Deadline::from_now(Duration::from_secs(10)),
+            output_offsets: (0..executor.schema.len() as u32).collect(),
+            out_most_executor: Box::new(executor),
+            config,
+            collect_exec_summary: false,
+            exec_stats: ExecuteStats::new(1),
+            stream_row_limit: 1000,
+            encode_type: EncodeType::TypeDefault,
+            paging_size: None,
+            quota_limiter: Arc::new(QuotaLimiter::default()),
+            memory_manager,
+            response_memory,
+            stream_pending_rows: VecDeque::new(),
+            stream_source_drained: false,
+            baseline_metrics: BaselineMetrics::default(),
+        }
+    }
+
+    fn limit_executor(limit: u64, partition_by: Vec<Expr>) -> tipb::Executor {
+        let mut d = Limit::default();
+        d.set_limit(limit);
+        d.set_partition_by(partition_by.into_iter().map(pb_item).collect());
+        let mut ed = tipb::Executor::default();
+        ed.set_tp(ExecType::TypeLimit);
+        ed.set_limit(d);
+        ed
+    }
+
+    fn pb_item(expr: Expr) -> tipb::ByItem {
+        let mut item = tipb::ByItem::default();
+        item.set_expr(expr);
+        item
+    }
+
+    fn top_n_executor(limit: u64, partition_by: Vec<Expr>, order_by: Vec<Expr>) -> tipb::Executor {
+        let mut d = TopN::default();
+        d.set_limit(limit);
+        d.set_partition_by(partition_by.into_iter().map(pb_item).collect());
+        d.set_order_by(order_by.into_iter().map(pb_item).collect());
+        let mut ed = tipb::Executor::default();
+        ed.set_tp(ExecType::TypeTopN);
+        ed.set_top_n(d);
+        ed
+    }
+
+    fn column_ref(offset: i64) -> Expr {
+        let mut e = Expr::default();
+        e.set_tp(tipb::ExprType::ColumnRef);
+        e.set_val(offset.to_be_bytes().to_vec());
+        e
+    }
+
+    #[test]
+    fn test_distinct_soft_limit_none_when_no_next_executor() {
+        assert_eq!(distinct_soft_limit(None, &[column_ref(0)]), None);
+    }
+
+    #[test]
+    fn test_distinct_soft_limit_from_plain_limit() {
+        let next = limit_executor(10, vec![]);
+        assert_eq!(distinct_soft_limit(Some(&next), &[column_ref(0)]), Some(10));
+    }
+
+    #[test]
+    fn test_distinct_soft_limit_rejects_partitioned_limit() {
+        let next = limit_executor(10, vec![column_ref(0)]);
+        assert_eq!(distinct_soft_limit(Some(&next), &[column_ref(0)]), None);
+    }
+
+    #[test]
+    fn test_distinct_soft_limit_from_top_n_with_order_by_prefix_of_group_by() {
+        let group_by = vec![column_ref(0), column_ref(1)];
+        let next = top_n_executor(5, vec![], vec![column_ref(0)]);
+        assert_eq!(distinct_soft_limit(Some(&next), &group_by), Some(5));
+    }
+
+    #[test]
+    fn test_distinct_soft_limit_rejects_top_n_whose_order_by_is_not_a_prefix() {
+        let group_by = vec![column_ref(0), column_ref(1)];
+        // order_by references column 1 first, which isn't the group_by
+        // prefix (column 0), so peeking past it must not push a limit
+        // down into the aggregation.
+        let next = top_n_executor(5, vec![], vec![column_ref(1)]);
+        assert_eq!(distinct_soft_limit(Some(&next), &group_by), None);
+    }
+
+    #[test]
+    fn test_distinct_soft_limit_rejects_top_n_with_more_order_by_than_group_by() {
+        let group_by = vec![column_ref(0)];
+        let next = top_n_executor(5, vec![], vec![column_ref(0), column_ref(1)]);
+        assert_eq!(distinct_soft_limit(Some(&next), &group_by), None);
+    }
+
+    #[test]
+    fn test_distinct_soft_limit_rejects_partitioned_top_n() {
+        let group_by = vec![column_ref(0)];
+        let next = top_n_executor(5, vec![column_ref(0)], vec![column_ref(0)]);
+        assert_eq!(distinct_soft_limit(Some(&next), &group_by), None);
+    }
+
+    #[test]
+    fn test_distinct_soft_limit_none_for_unrelated_executor() {
+        let mut next = tipb::Executor::default();
+        next.set_tp(ExecType::TypeSelection);
+        assert_eq!(distinct_soft_limit(Some(&next), &[column_ref(0)]), None);
+    }
+
+    #[test]
+    fn test_sort_fetch_limit_none_when_no_next_executor() {
+        assert_eq!(sort_fetch_limit(None), None);
+    }
+
+    #[test]
+    fn test_sort_fetch_limit_from_plain_limit() {
+        let next = limit_executor(5, vec![]);
+        assert_eq!(sort_fetch_limit(Some(&next)), Some(5));
+    }
+
+    #[test]
+    fn test_sort_fetch_limit_rejects_partitioned_limit() {
+        let next = limit_executor(5, vec![column_ref(0)]);
+        assert_eq!(sort_fetch_limit(Some(&next)), None);
+    }
+
+    #[test]
+    fn test_sort_fetch_limit_none_for_non_limit_executor() {
+        let next = top_n_executor(5, vec![], vec![column_ref(0)]);
+        assert_eq!(sort_fetch_limit(Some(&next)), None);
+    }
+
+    /// With no prior chunk to measure bytes-per-row from (the first grow
+    /// of a request), growth is purely geometric.
+    #[test]
+    fn test_grow_batch_size_is_geometric_without_a_byte_estimate() {
+        let mut batch_size = 32;
+        grow_batch_size(&mut batch_size, 0, 0, 1024 * 1024);
+        assert_eq!(batch_size, 64);
+    }
+
+    /// A wide-row chunk must have its next batch size capped so the
+    /// *projected* encoded size stays under `target_chunk_bytes`, even
+    /// though the geometric doubling alone would pick a larger batch.
+    #[test]
+    fn test_grow_batch_size_caps_by_target_chunk_bytes_for_wide_rows() {
+        let mut batch_size = 100;
+        // 1000 bytes / 10 rows = 100 bytes/row; a 1000-byte target allows
+        // only 10 rows next, far below the geometric doubling to 200.
+        grow_batch_size(&mut batch_size, 1000, 10, 1000);
+        assert_eq!(batch_size, 10);
+    }
+
+    #[test]
+    fn test_grow_batch_size_never_exceeds_batch_max_size() {
+        let mut batch_size = BATCH_MAX_SIZE;
+        grow_batch_size(&mut batch_size, 1, 1, usize::MAX);
+        assert_eq!(batch_size, BATCH_MAX_SIZE);
+    }
+
+    #[test]
+    fn test_grow_batch_size_byte_cap_floors_at_one_row() {
+        let mut batch_size = 32;
+        // A single previous row already far exceeds the byte target, but
+        // the next batch must still request at least one row.
+        grow_batch_size(&mut batch_size, 10 * 1024 * 1024, 1, 1024);
+        assert_eq!(batch_size, 1);
+    }
+
+    /// A response buffer budget too small for even one encoded batch must
+    /// fail the request with `ResourceExhausted` rather than growing
+    /// `chunk.mut_rows_data()` without bound.
+    #[tokio::test]
+    async fn test_handle_request_denies_when_response_memory_budget_exhausted() {
+        let executor = MockExecutor::new(
+            int_schema(),
+            vec![int_batch(&(0..50).collect::<Vec<_>>(), true)],
+        );
+        let config = Arc::new(EvalConfig::default());
+        let memory_manager = MemoryManager::new(1);
+        let mut runner = test_runner(executor, config, memory_manager);
+
+        let err = runner.handle_request().await.unwrap_err();
+        assert!(
+            matches!(err, Error::ResourceExhausted(_)),
+            "expected ResourceExhausted, got {:?}",
+            err
+        );
+    }
+
+    /// The pipelined path prefetches the next batch while encoding the
+    /// current one, but must still surface every row, in every chunk, in
+    /// the same order a strictly-serial fetch/encode loop would.
+    #[tokio::test]
+    async fn test_handle_request_pipelined_preserves_all_rows_across_batches() {
+        let executor = MockExecutor::new(
+            int_schema(),
+            vec![int_batch(&[1, 2, 3], false), int_batch(&[4, 5], true)],
+        );
+        let mut config = EvalConfig::default();
+        config.batch_prefetch_depth = 1;
+        let config = Arc::new(config);
+        let memory_manager = MemoryManager::unbounded();
+        let mut runner = test_runner(executor, config, memory_manager);
+
+        let (resp, _range) = runner.handle_request().await.unwrap();
+
+        // One chunk per fetched batch, in fetch order, with no rows
+        // dropped or duplicated by the prefetch overlap.
+        assert_eq!(resp.get_chunks().len(), 2);
+        assert_eq!(runner.baseline_metrics.output_rows, 5);
+    }
+
+    /// Every streaming response chunk but the last must carry exactly
+    /// `stream_num_rows_per_chunk` rows, with overflow from whatever
+    /// `next_batch` happened to return carried into `stream_pending_rows`
+    /// for the next call.
+    #[tokio::test]
+    async fn test_streaming_repartition_emits_exact_row_count_per_chunk() {
+        let executor = MockExecutor::new(
+            int_schema(),
+            vec![
+                int_batch(&[1, 2], false),
+                int_batch(&[3, 4], false),
+                int_batch(&[5, 6], true),
+            ],
+        );
+        let mut config = EvalConfig::default();
+        config.stream_num_rows_per_chunk = 3;
+        let config = Arc::new(config);
+        let memory_manager = MemoryManager::unbounded();
+        let mut runner = test_runner(executor, config, memory_manager);
+
+        let (first, drained1) = runner.handle_streaming_request().await.unwrap();
+        assert!(first.is_some());
+        // 2 + 2 = 4 rows pulled, 3 emitted, 1 left over as overflow.
+        assert_eq!(runner.stream_pending_rows.len(), 1);
+        assert!(!drained1);
+
+        let (second, drained2) = runner.handle_streaming_request().await.unwrap();
+        assert!(second.is_some());
+        // 1 pending + 2 from the final (draining) batch = 3, all emitted.
+        assert_eq!(runner.stream_pending_rows.len(), 0);
+        assert!(drained2);
+    }
+
+    /// `attach_baseline_metrics` must land on the *outermost* executor's
+    /// summary — the last entry, per `summary_per_executor`'s
+    /// innermost-to-outermost order — since that's the one whose
+    /// fetch+encode work the runner's timers actually measured, and must
+    /// leave every other executor's summary untouched.
+    #[test]
+    fn test_attach_baseline_metrics_sets_only_the_outermost_summary() {
+        let mut summaries = vec![
+            ExecutorExecutionSummary::default(),
+            ExecutorExecutionSummary::default(),
+        ];
+        let metrics = BaselineMetrics {
+            elapsed_compute_ns: 42,
+            output_rows: 7,
+            output_bytes: 99,
+        };
+
+        attach_baseline_metrics(&mut summaries, &metrics);
+
+        assert_eq!(summaries[0].get_elapsed_compute_ns(), 0);
+        assert_eq!(summaries[1].get_elapsed_compute_ns(), 42);
+        assert_eq!(summaries[1].get_output_rows(), 7);
+        assert_eq!(summaries[1].get_output_bytes(), 99);
+    }
+
+    #[test]
+    fn test_attach_baseline_metrics_no_op_on_empty_summaries() {
+        let mut summaries = vec![];
+        attach_baseline_metrics(&mut summaries, &BaselineMetrics::default());
+        assert!(summaries.is_empty());
+    }
+
+    /// For narrow rows the byte estimate allows a much larger batch than
+    /// the geometric doubling would pick; the geometric growth must stay
+    /// the upper bound, not be overridden upward by the byte estimate.
+    #[test]
+    fn test_grow_batch_size_byte_estimate_never_grows_past_geometric_bound() {
+        let mut batch_size = 32;
+        // 32 rows in 320 bytes = 10 bytes/row; a 1 MiB target would allow
+        // over 100,000 rows, but this step may still only double to 64.
+        grow_batch_size(&mut batch_size, 320, 32, 1024 * 1024);
+        assert_eq!(batch_size, 64);
+    }
+
+    /// Repeated growth for a stable wide-row workload must converge to,
+    /// and then stay at, the byte-targeted size instead of oscillating or
+    /// climbing past it on subsequent calls.
+    #[test]
+    fn test_grow_batch_size_converges_and_holds_for_a_stable_wide_row_workload() {
+        let target_chunk_bytes = 1000;
+        let avg_row_bytes = 50;
+        let mut batch_size = 32;
+
+        for _ in 0..10 {
+            let last_chunk_bytes = batch_size * avg_row_bytes;
+            grow_batch_size(&mut batch_size, last_chunk_bytes, batch_size, target_chunk_bytes);
+            assert!(batch_size * avg_row_bytes <= target_chunk_bytes.max(avg_row_bytes));
         }
+        // Converged to the byte-targeted row count and holds there.
+        assert_eq!(batch_size, target_chunk_bytes / avg_row_bytes);
+        let converged = batch_size;
+        grow_batch_size(&mut batch_size, converged * avg_row_bytes, converged, target_chunk_bytes);
+        assert_eq!(batch_size, converged);
     }
 }
@@ -0,0 +1,596 @@
+// Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::{cmp::Ordering, collections::BinaryHeap, convert::TryFrom, io::Read, sync::Arc};
+
+use async_trait::async_trait;
+use tidb_query_common::{Result, storage::IntervalRange};
+use tidb_query_datatype::{
+    EvalType, FieldTypeAccessor,
+    codec::{
+        batch::{LazyBatchColumn, LazyBatchColumnVec},
+        datum::{self, Datum},
+    },
+    expr::{EvalConfig, EvalContext},
+};
+use tidb_query_expr::{RpnExpression, RpnExpressionBuilder, types::BATCH_MAX_SIZE};
+use tikv_util::{config::ReadableSize, deadline::Deadline};
+use tipb::{Expr, FieldType};
+
+use super::interface::{BatchExecIsDrain, BatchExecuteResult, BatchExecutor, ExecuteStats};
+
+/// In-memory budget, in estimated bytes, for a single sort run before
+/// [`BatchSortExecutor`] spills it to a temporary file. Large enough that
+/// most sorts never spill; small enough that a spill multiplies cleanly
+/// rather than pushing the whole table into one run.
+const DEFAULT_SORT_SPILL_THRESHOLD: usize = ReadableSize::mb(64).0 as usize;
+
+/// Rough per-`Datum` overhead, used to approximate a run's resident size
+/// without walking every value's exact encoded length on every push.
+const DATUM_OVERHEAD_ESTIMATE: usize = 16;
+
+/// One row pulled out of the child's batches and decoupled from them, so
+/// rows from many batches can be pooled into a single sort run: the row's
+/// memcomparable order key (for merging without re-evaluating
+/// expressions) and its full column values (for re-materializing the row
+/// once it is time to emit it).
+struct SortRow {
+    order_key: Vec<u8>,
+    values: Vec<Datum>,
+}
+
+impl SortRow {
+    fn estimated_size(&self) -> usize {
+        self.order_key.len()
+            + self
+                .values
+                .iter()
+                .map(|d| d.approximate_mem_size() + DATUM_OVERHEAD_ESTIMATE)
+                .sum::<usize>()
+    }
+
+    /// Writes this row as `[key_len][key][row_len][row]`, with the row's
+    /// values encoded by `datum::encode_value`.
+    fn encode(&self, ctx: &mut EvalContext, buf: &mut Vec<u8>) -> Result<()> {
+        buf.extend_from_slice(&(self.order_key.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&self.order_key);
+
+        let mut row_buf = Vec::new();
+        datum::encode_value(ctx, &mut row_buf, &self.values)?;
+        buf.extend_from_slice(&(row_buf.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&row_buf);
+        Ok(())
+    }
+}
+
+/// Builds the memcomparable order key for one row: the per-column
+/// memcomparable encoding of each order-by expression's value,
+/// concatenated in order-by order. A descending column has its bytes
+/// bit-complemented, which inverts byte-lexicographic order for that
+/// column's contribution without disturbing the others — the same trick
+/// TiKV's own index encoding uses for descending index columns. The
+/// result is directly comparable with `Ord`, so merging spilled runs
+/// never needs `ctx` again.
+fn order_key_of(
+    ctx: &mut EvalContext,
+    order_exprs: &[RpnExpression],
+    order_is_desc: &[bool],
+    schema: &[FieldType],
+    physical_columns: &mut LazyBatchColumnVec,
+    logical_row: usize,
+) -> Result<Vec<u8>> {
+    let mut key = Vec::new();
+    for (expr, desc) in order_exprs.iter().zip(order_is_desc) {
+        let value = expr
+            .eval(ctx, schema, physical_columns, &[logical_row], 1)?
+            .eval_single_row_as_datum(ctx)?;
+        let mut column_key = datum::encode_key(ctx, &[value])?;
+        if *desc {
+            for b in &mut column_key {
+                *b = !*b;
+            }
+        }
+        key.append(&mut column_key);
+    }
+    Ok(key)
+}
+
+/// Reads every value of a logical row out of a batch of physical columns,
+/// parking it as an owned `Datum` per column so the row survives past
+/// the batch it arrived in.
+fn row_values_of(
+    schema: &[FieldType],
+    physical_columns: &LazyBatchColumnVec,
+    logical_row: usize,
+) -> Result<Vec<Datum>> {
+    schema
+        .iter()
+        .enumerate()
+        .map(|(col_idx, field_type)| {
+            physical_columns[col_idx]
+                .decoded()
+                .get_scalar_ref(physical_columns.logical_rows()[logical_row])
+                .to_datum(field_type)
+        })
+        .collect()
+}
+
+/// Rebuilds a `BATCH_MAX_SIZE`-sized output batch from already-sorted,
+/// fully materialized rows.
+fn build_batch(schema: &[FieldType], rows: &[Vec<Datum>]) -> Result<LazyBatchColumnVec> {
+    let mut columns = LazyBatchColumnVec::with_capacity(schema.len());
+    for (col_idx, field_type) in schema.iter().enumerate() {
+        let eval_type = EvalType::try_from(field_type.as_accessor().tp())
+            .unwrap_or(EvalType::Bytes);
+        let mut column = LazyBatchColumn::decoded_with_capacity_and_tp(rows.len(), eval_type);
+        for row in rows {
+            column.mut_decoded().push_datum(&row[col_idx])?;
+        }
+        columns.push(column);
+    }
+    Ok(columns)
+}
+
+/// Accumulates rows pulled from the child executor into a single
+/// in-memory run, spilling to a temporary file once `estimated_bytes`
+/// crosses the configured budget.
+#[derive(Default)]
+struct AccumulatingRun {
+    rows: Vec<SortRow>,
+    estimated_bytes: usize,
+}
+
+impl AccumulatingRun {
+    fn push(&mut self, row: SortRow) {
+        self.estimated_bytes += row.estimated_size();
+        self.rows.push(row);
+    }
+
+    /// Sorts the accumulated rows by their order key and writes them, in
+    /// that order, to a temporary file as a sequence of
+    /// `[key_len][key][row_len][row]` entries, as written by
+    /// [`SortRow::encode`].
+    fn spill(&mut self, ctx: &mut EvalContext) -> Result<std::fs::File> {
+        let mut rows = std::mem::take(&mut self.rows);
+        self.estimated_bytes = 0;
+        rows.sort_unstable_by(|a, b| a.order_key.cmp(&b.order_key));
+
+        let mut buf = Vec::new();
+        for row in &rows {
+            row.encode(ctx, &mut buf)?;
+        }
+        let mut file = tempfile::tempfile()?;
+        std::io::Write::write_all(&mut file, &buf)?;
+        std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(0))?;
+        Ok(file)
+    }
+
+    fn sort_in_place(&mut self) {
+        self.rows.sort_unstable_by(|a, b| a.order_key.cmp(&b.order_key));
+    }
+}
+
+/// One run being merged: either a spilled file, read back incrementally
+/// one entry at a time, or the final in-memory run.
+enum MergeRun {
+    File(std::io::BufReader<std::fs::File>),
+    Memory(std::vec::IntoIter<SortRow>),
+}
+
+impl MergeRun {
+    fn next(&mut self) -> Result<Option<SortRow>> {
+        match self {
+            MergeRun::Memory(iter) => Ok(iter.next()),
+            MergeRun::File(reader) => {
+                let mut len_buf = [0u8; 4];
+                if let Err(e) = reader.read_exact(&mut len_buf) {
+                    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                        return Ok(None);
+                    }
+                    return Err(e.into());
+                }
+                let key_len = u32::from_be_bytes(len_buf) as usize;
+                let mut order_key = vec![0u8; key_len];
+                reader.read_exact(&mut order_key)?;
+
+                reader.read_exact(&mut len_buf)?;
+                let row_len = u32::from_be_bytes(len_buf) as usize;
+                let mut row_buf = vec![0u8; row_len];
+                reader.read_exact(&mut row_buf)?;
+                let mut cursor = &row_buf[..];
+                let values = datum::decode(&mut cursor)?;
+                Ok(Some(SortRow { order_key, values }))
+            }
+        }
+    }
+}
+
+/// One candidate row in the k-way merge's min-heap, ordered so
+/// `BinaryHeap` (a max-heap) pops the row with the smallest order key
+/// first — the "loser tree" among the runs' current heads.
+struct HeapItem {
+    row: SortRow,
+    run_idx: usize,
+}
+
+impl PartialEq for HeapItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.row.order_key == other.row.order_key
+    }
+}
+impl Eq for HeapItem {}
+impl PartialOrd for HeapItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for HeapItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.row.order_key.cmp(&self.row.order_key)
+    }
+}
+
+/// Lazily pulls a fully sorted stream out of every spilled run plus the
+/// final in-memory run via a k-way merge, driven on demand by
+/// `BatchSortExecutor::next_batch`.
+struct MergingRuns {
+    runs: Vec<MergeRun>,
+    heap: BinaryHeap<HeapItem>,
+    primed: bool,
+}
+
+impl MergingRuns {
+    fn new(runs: Vec<MergeRun>) -> Self {
+        Self {
+            runs,
+            heap: BinaryHeap::new(),
+            primed: false,
+        }
+    }
+
+    fn prime(&mut self) -> Result<()> {
+        for idx in 0..self.runs.len() {
+            if let Some(row) = self.runs[idx].next()? {
+                self.heap.push(HeapItem { row, run_idx: idx });
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<SortRow>> {
+        if !self.primed {
+            self.prime()?;
+            self.primed = true;
+        }
+        let Some(HeapItem { row, run_idx }) = self.heap.pop() else {
+            return Ok(None);
+        };
+        if let Some(next_row) = self.runs[run_idx].next()? {
+            self.heap.push(HeapItem {
+                row: next_row,
+                run_idx,
+            });
+        }
+        Ok(Some(row))
+    }
+}
+
+enum SortState {
+    Accumulating(AccumulatingRun),
+    Merging(MergingRuns),
+}
+
+/// Batch executor for `ExecType::TypeSort`: a full, unbounded sort by the
+/// given order-by expressions, backed by an external merge sort so a
+/// large input does not have to fit in memory. Pulls and pools every
+/// batch from its child on the first call to `next_batch`, spilling runs
+/// to temporary files as the in-memory budget is exceeded, then answers
+/// subsequent calls from a k-way merge across whatever runs resulted —
+/// a single in-memory run if nothing ever spilled.
+pub struct BatchSortExecutor<Src: BatchExecutor> {
+    src: Src,
+    context: EvalContext,
+    order_exprs: Vec<RpnExpression>,
+    order_is_desc: Vec<bool>,
+    state: SortState,
+    /// Runs already spilled to disk, waiting to be handed to
+    /// `MergingRuns` once the child is fully drained.
+    spilled_runs: Vec<MergeRun>,
+    /// Checked once per child batch pulled in `drain_and_sort`, since that
+    /// loop runs to completion inside a single `next_batch` call and the
+    /// runner's own per-`next_batch` deadline check never gets a chance to
+    /// fire in between.
+    deadline: Deadline,
+}
+
+impl<Src: BatchExecutor> BatchSortExecutor<Src> {
+    pub fn check_supported(descriptor: &tipb::Sort) -> Result<()> {
+        if descriptor.get_by_items().is_empty() {
+            return Err(other_err!("Missing Sort By column"));
+        }
+        for item in descriptor.get_by_items() {
+            RpnExpressionBuilder::check_expr_tree_supported(item.get_expr())?;
+        }
+        Ok(())
+    }
+
+    pub fn new(
+        config: Arc<EvalConfig>,
+        src: Src,
+        order_exprs_def: Vec<Expr>,
+        order_is_desc: Vec<bool>,
+        deadline: Deadline,
+    ) -> Result<Self> {
+        assert_eq!(order_exprs_def.len(), order_is_desc.len());
+        let mut context = EvalContext::new(config);
+        let schema_len = src.schema().len();
+        let order_exprs = order_exprs_def
+            .into_iter()
+            .map(|def| RpnExpressionBuilder::build_from_expr_tree(def, &mut context, schema_len))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            src,
+            context,
+            order_exprs,
+            order_is_desc,
+            state: SortState::Accumulating(AccumulatingRun::default()),
+            spilled_runs: Vec::new(),
+            deadline,
+        })
+    }
+
+    /// Drains the child completely, spilling runs as needed, then leaves
+    /// `self.state` holding the merge ready to be pulled from. A no-op on
+    /// every call after the first.
+    async fn drain_and_sort(&mut self) -> Result<()> {
+        let SortState::Accumulating(_) = &self.state else {
+            return Ok(());
+        };
+
+        loop {
+            // `drain_and_sort` runs to completion inside a single
+            // `next_batch` call, so the runner's usual per-`next_batch`
+            // deadline check only ever fires once, before this loop even
+            // starts. Re-check it here, once per child batch pulled, so an
+            // unbounded `ORDER BY` can't run arbitrarily long past its
+            // deadline.
+            self.deadline.check()?;
+
+            let mut result = self.src.next_batch(BATCH_MAX_SIZE).await;
+            let is_drained = result.is_drained?;
+
+            for i in 0..result.logical_rows.len() {
+                let order_key = order_key_of(
+                    &mut self.context,
+                    &self.order_exprs,
+                    &self.order_is_desc,
+                    self.src.schema(),
+                    &mut result.physical_columns,
+                    i,
+                )?;
+                let values = row_values_of(self.src.schema(), &result.physical_columns, i)?;
+                let SortState::Accumulating(run) = &mut self.state else {
+                    unreachable!("state cannot change mid-drain")
+                };
+                run.push(SortRow { order_key, values });
+            }
+
+            let SortState::Accumulating(run) = &mut self.state else {
+                unreachable!("state cannot change mid-drain")
+            };
+            if run.estimated_bytes >= DEFAULT_SORT_SPILL_THRESHOLD {
+                let file = run.spill(&mut self.context)?;
+                self.spilled_runs
+                    .push(MergeRun::File(std::io::BufReader::new(file)));
+            }
+
+            // Keeps a multi-batch drain from monopolizing the executor
+            // thread between the deadline checks above.
+            tokio::task::yield_now().await;
+
+            if is_drained.stop() {
+                break;
+            }
+        }
+
+        let SortState::Accumulating(mut run) = std::mem::replace(
+            &mut self.state,
+            SortState::Merging(MergingRuns::new(Vec::new())),
+        ) else {
+            unreachable!("state was just checked to be Accumulating")
+        };
+        run.sort_in_place();
+        let mut runs = std::mem::take(&mut self.spilled_runs);
+        runs.push(MergeRun::Memory(run.rows.into_iter()));
+        self.state = SortState::Merging(MergingRuns::new(runs));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<Src: BatchExecutor> BatchExecutor for BatchSortExecutor<Src> {
+    type StorageStats = Src::StorageStats;
+
+    fn schema(&self) -> &[FieldType] {
+        self.src.schema()
+    }
+
+    async fn next_batch(&mut self, scan_rows: usize) -> BatchExecuteResult {
+        if let Err(e) = self.drain_and_sort().await {
+            return BatchExecuteResult {
+                physical_columns: LazyBatchColumnVec::empty(),
+                logical_rows: Vec::new(),
+                warnings: self.context.take_warnings(),
+                is_drained: Err(e),
+            };
+        }
+
+        let SortState::Merging(merge) = &mut self.state else {
+            unreachable!("drain_and_sort always leaves the state Merging")
+        };
+
+        let mut rows = Vec::with_capacity(scan_rows.min(BATCH_MAX_SIZE));
+        let mut is_drained = false;
+        while rows.len() < scan_rows.min(BATCH_MAX_SIZE) {
+            match merge.next() {
+                Ok(Some(row)) => rows.push(row.values),
+                Ok(None) => {
+                    is_drained = true;
+                    break;
+                }
+                Err(e) => {
+                    return BatchExecuteResult {
+                        physical_columns: LazyBatchColumnVec::empty(),
+                        logical_rows: Vec::new(),
+                        warnings: self.context.take_warnings(),
+                        is_drained: Err(e),
+                    };
+                }
+            }
+        }
+
+        let schema = self.src.schema();
+        let logical_rows = (0..rows.len()).collect();
+        let physical_columns = match build_batch(schema, &rows) {
+            Ok(columns) => columns,
+            Err(e) => {
+                return BatchExecuteResult {
+                    physical_columns: LazyBatchColumnVec::empty(),
+                    logical_rows: Vec::new(),
+                    warnings: self.context.take_warnings(),
+                    is_drained: Err(e),
+                };
+            }
+        };
+
+        BatchExecuteResult {
+            physical_columns,
+            logical_rows,
+            warnings: self.context.take_warnings(),
+            is_drained: Ok(if is_drained {
+                BatchExecIsDrain::Drain
+            } else {
+                BatchExecIsDrain::Remain
+            }),
+        }
+    }
+
+    fn collect_exec_stats(&mut self, dest: &mut ExecuteStats) {
+        self.src.collect_exec_stats(dest);
+    }
+
+    fn collect_storage_stats(&mut self, dest: &mut Self::StorageStats) {
+        self.src.collect_storage_stats(dest);
+    }
+
+    fn take_scanned_range(&mut self) -> IntervalRange {
+        self.src.take_scanned_range()
+    }
+
+    fn can_be_cached(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tidb_query_datatype::codec::datum::Datum;
+
+    use super::*;
+
+    fn row(order_key: &[u8], value: i64) -> SortRow {
+        SortRow {
+            order_key: order_key.to_vec(),
+            values: vec![Datum::I64(value)],
+        }
+    }
+
+    /// `AccumulatingRun::sort_in_place` is the no-spill path: everything
+    /// stayed in memory, so `drain_and_sort` hands its rows straight to
+    /// `MergingRuns` as a single `MergeRun::Memory`.
+    #[test]
+    fn test_accumulating_run_sort_in_place_orders_by_key() {
+        let mut run = AccumulatingRun::default();
+        run.push(row(b"c", 3));
+        run.push(row(b"a", 1));
+        run.push(row(b"b", 2));
+
+        run.sort_in_place();
+
+        let keys: Vec<_> = run.rows.iter().map(|r| r.order_key.clone()).collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+    }
+
+    /// The spill path: rows written out via `AccumulatingRun::spill` must
+    /// read back, through `MergeRun::File`, in the same sorted order and
+    /// with their values intact.
+    #[test]
+    fn test_spill_round_trip_preserves_order_and_values() {
+        let config = Arc::new(EvalConfig::default());
+        let mut ctx = EvalContext::new(config);
+
+        let mut run = AccumulatingRun::default();
+        run.push(row(b"z", 30));
+        run.push(row(b"x", 10));
+        run.push(row(b"y", 20));
+
+        let file = run.spill(&mut ctx).unwrap();
+        let mut reader = MergeRun::File(std::io::BufReader::new(file));
+
+        let mut seen = Vec::new();
+        while let Some(r) = reader.next().unwrap() {
+            seen.push((r.order_key, r.values));
+        }
+        assert_eq!(
+            seen,
+            vec![
+                (b"x".to_vec(), vec![Datum::I64(10)]),
+                (b"y".to_vec(), vec![Datum::I64(20)]),
+                (b"z".to_vec(), vec![Datum::I64(30)]),
+            ]
+        );
+    }
+
+    /// The general case a spilling sort actually relies on: several runs
+    /// (a mix of spilled files and the final in-memory run) interleaved
+    /// out of order must still merge into one globally sorted sequence.
+    #[test]
+    fn test_merging_runs_k_way_merge_is_globally_sorted() {
+        let config = Arc::new(EvalConfig::default());
+        let mut ctx = EvalContext::new(config);
+
+        let mut run_a = AccumulatingRun::default();
+        run_a.push(row(b"b", 2));
+        run_a.push(row(b"e", 5));
+        let file_a = run_a.spill(&mut ctx).unwrap();
+
+        let mut run_b = AccumulatingRun::default();
+        run_b.push(row(b"a", 1));
+        run_b.push(row(b"d", 4));
+        let file_b = run_b.spill(&mut ctx).unwrap();
+
+        let mut memory_run = vec![row(b"c", 3), row(b"f", 6)];
+        memory_run.sort_unstable_by(|a, b| a.order_key.cmp(&b.order_key));
+
+        let mut merging = MergingRuns::new(vec![
+            MergeRun::File(std::io::BufReader::new(file_a)),
+            MergeRun::File(std::io::BufReader::new(file_b)),
+            MergeRun::Memory(memory_run.into_iter()),
+        ]);
+
+        let mut merged = Vec::new();
+        while let Some(r) = merging.next().unwrap() {
+            merged.push(r.order_key);
+        }
+        assert_eq!(
+            merged,
+            vec![
+                b"a".to_vec(),
+                b"b".to_vec(),
+                b"c".to_vec(),
+                b"d".to_vec(),
+                b"e".to_vec(),
+                b"f".to_vec(),
+            ]
+        );
+    }
+}
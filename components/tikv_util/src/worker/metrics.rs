@@ -0,0 +1,45 @@
+// Copyright 2017 TiKV Project Authors. Licensed under Apache-2.0.
+
+use lazy_static::lazy_static;
+use prometheus::*;
+
+lazy_static! {
+    pub static ref WORKER_PENDING_TASK_VEC: IntGaugeVec = register_int_gauge_vec!(
+        "tikv_worker_pending_task_total",
+        "Current number of pending and running tasks",
+        &["name"]
+    )
+    .unwrap();
+    pub static ref WORKER_HANDLED_TASK_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_worker_handled_task_total",
+        "Total number of tasks handled",
+        &["name"]
+    )
+    .unwrap();
+    pub static ref WORKER_POLL_DURATION_VEC: HistogramVec = register_histogram_vec!(
+        "tikv_worker_poll_duration_seconds",
+        "Bucketed histogram of the time spent running a single task in a worker",
+        &["name"],
+        exponential_buckets(0.0001, 2.0, 20).unwrap() // 0.1ms ~ 52s
+    )
+    .unwrap();
+    pub static ref WORKER_WAIT_DURATION_VEC: HistogramVec = register_histogram_vec!(
+        "tikv_worker_wait_duration_seconds",
+        "Bucketed histogram of the time a task spent queued before it started running",
+        &["name"],
+        exponential_buckets(0.0001, 2.0, 20).unwrap() // 0.1ms ~ 52s
+    )
+    .unwrap();
+    pub static ref WORKER_BUSY_SECONDS_VEC: CounterVec = register_counter_vec!(
+        "tikv_worker_busy_seconds_total",
+        "Cumulative time a worker spent running tasks, for computing busy ratio",
+        &["name"]
+    )
+    .unwrap();
+    pub static ref WORKER_PENDING_TASK_HIGH_WATER_VEC: IntGaugeVec = register_int_gauge_vec!(
+        "tikv_worker_pending_task_high_water",
+        "High-water mark of the number of pending tasks observed for a worker",
+        &["name"]
+    )
+    .unwrap();
+}
@@ -6,9 +6,10 @@ use std::{
     fmt::{self, Debug, Display, Formatter},
     future::Future,
     sync::{
-        Arc,
-        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     },
+    task::Waker,
     time::{Duration, Instant},
 };
 
@@ -16,7 +17,7 @@ use futures::{
     channel::mpsc::{UnboundedReceiver, UnboundedSender, unbounded},
     compat::{Future01CompatExt, Stream01CompatExt},
     executor::block_on,
-    future::FutureExt,
+    future::{self, FutureExt},
     stream::StreamExt,
 };
 use prometheus::{IntCounter, IntGauge};
@@ -88,7 +89,7 @@ impl<R: Runnable + 'static> Drop for RunnableWrapper<R> {
 }
 
 enum Msg<T: Display + Send> {
-    Task(T),
+    Task(T, Instant),
     Timeout,
 }
 
@@ -115,26 +116,43 @@ impl<T: Runnable> RunnableWithTimer for NoTimeoutRunnableWrapper<T> {
     }
 }
 
+/// A snapshot of a [`Scheduler`]'s queue-depth metrics, as returned by
+/// [`Scheduler::metrics`].
+#[derive(Debug, Clone, Copy)]
+pub struct SchedulerMetrics {
+    pub pending_tasks: usize,
+    pub pending_tasks_high_water: usize,
+}
+
 /// Scheduler provides interface to schedule task to underlying workers.
 pub struct Scheduler<T: Display + Send> {
     counter: Arc<AtomicUsize>,
+    high_water: Arc<AtomicUsize>,
     sender: UnboundedSender<Msg<T>>,
     pending_capacity: usize,
     metrics_pending_task_count: IntGauge,
+    metrics_pending_task_high_water: IntGauge,
+    waiters: Arc<Mutex<Vec<Waker>>>,
 }
 
 impl<T: Display + Send> Scheduler<T> {
     fn new(
         sender: UnboundedSender<Msg<T>>,
         counter: Arc<AtomicUsize>,
+        high_water: Arc<AtomicUsize>,
         pending_capacity: usize,
         metrics_pending_task_count: IntGauge,
+        metrics_pending_task_high_water: IntGauge,
+        waiters: Arc<Mutex<Vec<Waker>>>,
     ) -> Scheduler<T> {
         Scheduler {
             counter,
+            high_water,
             sender,
             pending_capacity,
             metrics_pending_task_count,
+            metrics_pending_task_high_water,
+            waiters,
         }
     }
 
@@ -155,10 +173,14 @@ impl<T: Display + Send> Scheduler<T> {
     /// Different from the `schedule` function, the task will still be scheduled
     /// if pending task number exceeds capacity.
     pub fn schedule_force(&self, task: T) -> Result<(), ScheduleError<T>> {
-        self.counter.fetch_add(1, Ordering::SeqCst);
+        let pending = self.counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let prev_high_water = self.high_water.fetch_max(pending, Ordering::Relaxed);
+        if pending > prev_high_water {
+            self.metrics_pending_task_high_water.set(pending as i64);
+        }
         self.metrics_pending_task_count.inc();
-        if let Err(e) = self.sender.unbounded_send(Msg::Task(task)) {
-            if let Msg::Task(t) = e.into_inner() {
+        if let Err(e) = self.sender.unbounded_send(Msg::Task(task, Instant::now())) {
+            if let Msg::Task(t, _) = e.into_inner() {
                 self.counter.fetch_sub(1, Ordering::SeqCst);
                 self.metrics_pending_task_count.dec();
                 return Err(ScheduleError::Stopped(t));
@@ -167,6 +189,79 @@ impl<T: Display + Send> Scheduler<T> {
         Ok(())
     }
 
+    /// Returns a snapshot of this scheduler's queue-depth metrics.
+    pub fn metrics(&self) -> SchedulerMetrics {
+        SchedulerMetrics {
+            pending_tasks: self.counter.load(Ordering::Acquire),
+            pending_tasks_high_water: self.high_water.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Schedules a task once the pending queue has room, waking up when a
+    /// previously queued task finishes instead of failing immediately like
+    /// [`schedule`](Self::schedule) does.
+    pub fn schedule_async(&self, task: T) -> impl Future<Output = Result<(), ScheduleError<T>>> + '_ {
+        let mut task = Some(task);
+        future::poll_fn(move |cx| {
+            if self.sender.is_closed() {
+                return std::task::Poll::Ready(Err(ScheduleError::Stopped(task.take().unwrap())));
+            }
+            if self.counter.load(Ordering::Acquire) < self.pending_capacity {
+                let t = task.take().unwrap();
+                return std::task::Poll::Ready(self.schedule_force(t));
+            }
+            // Register the waker *before* re-checking the counter: if a
+            // slot frees up (and `wake_waiters` drains the list) between
+            // the failed load above and this push, the re-check below
+            // still catches it. Without the re-check, that completion
+            // would drain a waiter list that doesn't contain us yet, and
+            // this task would sleep forever.
+            self.waiters.lock().unwrap().push(cx.waker().clone());
+            if self.counter.load(Ordering::Acquire) < self.pending_capacity {
+                let t = task.take().unwrap();
+                return std::task::Poll::Ready(self.schedule_force(t));
+            }
+            std::task::Poll::Pending
+        })
+    }
+
+    /// Schedules a task, retrying with exponential backoff
+    /// (`min(base * 2^attempt, max_delay)`) whenever the queue is full,
+    /// instead of failing on the first [`ScheduleError::Full`].
+    ///
+    /// Gives up and returns the error once `max_retries` have been
+    /// exhausted, or immediately if the worker has stopped.
+    pub async fn schedule_with_backoff(
+        &self,
+        mut task: T,
+        base: Duration,
+        max_delay: Duration,
+        max_retries: u32,
+    ) -> Result<(), ScheduleError<T>> {
+        let mut attempt = 0u32;
+        loop {
+            task = match self.schedule(task) {
+                Ok(()) => return Ok(()),
+                Err(ScheduleError::Stopped(t)) => return Err(ScheduleError::Stopped(t)),
+                Err(ScheduleError::Full(t)) => {
+                    if self.sender.is_closed() {
+                        return Err(ScheduleError::Stopped(t));
+                    }
+                    t
+                }
+            };
+            if attempt >= max_retries {
+                return Err(ScheduleError::Full(task));
+            }
+            let delay = base
+                .checked_mul(1u32 << attempt.min(20))
+                .unwrap_or(max_delay)
+                .min(max_delay);
+            let _ = GLOBAL_TIMER_HANDLE.delay(Instant::now() + delay).compat().await;
+            attempt += 1;
+        }
+    }
+
     /// Checks if underlying worker can't handle task immediately.
     pub fn is_busy(&self) -> bool {
         self.counter.load(Ordering::Acquire) > 0
@@ -185,14 +280,27 @@ impl<T: Display + Send> Clone for Scheduler<T> {
     fn clone(&self) -> Scheduler<T> {
         Scheduler {
             counter: Arc::clone(&self.counter),
+            high_water: Arc::clone(&self.high_water),
             sender: self.sender.clone(),
             pending_capacity: self.pending_capacity,
             metrics_pending_task_count: self.metrics_pending_task_count.clone(),
+            metrics_pending_task_high_water: self.metrics_pending_task_high_water.clone(),
+            waiters: Arc::clone(&self.waiters),
         }
     }
 }
 
+/// Wakes every scheduler task currently parked in
+/// [`Scheduler::schedule_async`], so they can re-check whether the pending
+/// queue has room now that a task has finished.
+fn wake_waiters(waiters: &Mutex<Vec<Waker>>) {
+    for waker in waiters.lock().unwrap().drain(..) {
+        waker.wake();
+    }
+}
+
 pub struct LazyWorker<T: Display + Send + 'static> {
+    name: String,
     scheduler: Scheduler<T>,
     worker: Worker,
     receiver: Option<UnboundedReceiver<Msg<T>>>,
@@ -218,6 +326,7 @@ impl<T: Display + Send + 'static> LazyWorker<T> {
     ) -> bool {
         if let Some(receiver) = self.receiver.take() {
             self.worker.start_with_timer_impl(
+                &self.name,
                 runner,
                 self.scheduler.sender.clone(),
                 receiver,
@@ -263,7 +372,7 @@ impl<T: Display + Send> ReceiverWrapper<T> {
     pub fn recv(&mut self) -> Option<T> {
         let msg = block_on(self.inner.next());
         match msg {
-            Some(Msg::Task(t)) => Some(t),
+            Some(Msg::Task(t, _)) => Some(t),
             _ => None,
         }
     }
@@ -274,7 +383,7 @@ impl<T: Display + Send> ReceiverWrapper<T> {
     ) -> Result<Option<T>, std::sync::mpsc::RecvTimeoutError> {
         let msg = block_on_timeout(self.inner.next(), timeout)
             .map_err(|_| std::sync::mpsc::RecvTimeoutError::Timeout)?;
-        if let Some(Msg::Task(t)) = msg {
+        if let Some(Msg::Task(t, _)) = msg {
             return Ok(Some(t));
         }
         Ok(None)
@@ -290,8 +399,11 @@ pub fn dummy_scheduler<T: Display + Send>() -> (Scheduler<T>, ReceiverWrapper<T>
         Scheduler::new(
             tx,
             Arc::new(AtomicUsize::new(0)),
+            Arc::new(AtomicUsize::new(0)),
             1000,
             WORKER_PENDING_TASK_VEC.with_label_values(&["dummy"]),
+            WORKER_PENDING_TASK_HIGH_WATER_VEC.with_label_values(&["dummy"]),
+            Arc::new(Mutex::new(Vec::new())),
         ),
         ReceiverWrapper { inner: rx },
     )
@@ -304,6 +416,7 @@ pub struct Builder<S: Into<String>> {
     min_thread_count: Option<usize>,
     max_thread_count: Option<usize>,
     pending_capacity: usize,
+    throttle: Option<Duration>,
 }
 
 impl<S: Into<String>> Builder<S> {
@@ -314,6 +427,7 @@ impl<S: Into<String>> Builder<S> {
             min_thread_count: None,
             max_thread_count: None,
             pending_capacity: usize::MAX,
+            throttle: None,
         }
     }
 
@@ -337,6 +451,17 @@ impl<S: Into<String>> Builder<S> {
         self
     }
 
+    /// Opts the worker into batched draining: instead of waking up once per
+    /// scheduled task, the run loop wakes on a fixed `quantum` tick and
+    /// greedily drains every task that is queued at that point. This
+    /// amortizes scheduler wakeups for high-throughput runners at the cost
+    /// of up to one quantum of latency.
+    #[must_use]
+    pub fn throttle(mut self, quantum: Duration) -> Self {
+        self.throttle = Some(quantum);
+        self
+    }
+
     pub fn create(self) -> Worker {
         let pool = YatpPoolBuilder::new(DefaultTicker::default())
             .name_prefix(self.name)
@@ -350,8 +475,13 @@ impl<S: Into<String>> Builder<S> {
             stop: Arc::new(AtomicBool::new(false)),
             pool,
             counter: Arc::new(AtomicUsize::new(0)),
+            high_water: Arc::new(AtomicUsize::new(0)),
             pending_capacity: self.pending_capacity,
             thread_count: self.core_thread_count,
+            throttle: self.throttle,
+            waiters: Arc::new(Mutex::new(Vec::new())),
+            next_task_id: Arc::new(AtomicU64::new(0)),
+            tasks: Arc::new(Mutex::new(Vec::new())),
         }
     }
 }
@@ -362,8 +492,59 @@ pub struct Worker {
     pool: FuturePool,
     pending_capacity: usize,
     counter: Arc<AtomicUsize>,
+    high_water: Arc<AtomicUsize>,
     stop: Arc<AtomicBool>,
     thread_count: usize,
+    throttle: Option<Duration>,
+    waiters: Arc<Mutex<Vec<Waker>>>,
+    next_task_id: Arc<AtomicU64>,
+    #[allow(clippy::type_complexity)]
+    tasks: Arc<
+        Mutex<
+            Vec<(
+                u64,
+                Arc<AtomicBool>,
+                Arc<Mutex<Option<Waker>>>,
+                Arc<Mutex<Option<futures::channel::oneshot::Receiver<()>>>>,
+            )>,
+        >,
+    >,
+}
+
+/// A handle to a future spawned via [`Worker::spawn_async_task`],
+/// [`Worker::spawn_interval_task`] or [`Worker::spawn_interval_async_task`].
+///
+/// Dropping the handle leaves the task running; call [`cancel`](Self::cancel)
+/// or [`join`](Self::join) to tear it down explicitly.
+pub struct TaskHandle {
+    cancelled: Arc<AtomicBool>,
+    waker: Arc<Mutex<Option<Waker>>>,
+    done: Arc<Mutex<Option<futures::channel::oneshot::Receiver<()>>>>,
+}
+
+impl TaskHandle {
+    /// Cooperatively cancels the task. The task observes this at its next
+    /// poll (for a one-shot task) or its next loop iteration (for an
+    /// interval task) and tears down from there.
+    ///
+    /// Also wakes the task's last-registered waker, so a one-shot task
+    /// parked on some external event that may never fire (e.g. waiting on a
+    /// channel nothing else will send on) is still repolled promptly
+    /// instead of leaving `join`/`Worker::stop` blocked on it indefinitely.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    /// Cancels the task and blocks until it has finished tearing down.
+    pub fn join(&self) {
+        self.cancel();
+        if let Some(done) = self.done.lock().unwrap().take() {
+            let _ = block_on(done);
+        }
+    }
 }
 
 impl Worker {
@@ -389,7 +570,10 @@ impl Worker {
         let name = name.into();
         let metrics_pending_task_count = WORKER_PENDING_TASK_VEC.with_label_values(&[&name]);
         let metrics_handled_task_count = WORKER_HANDLED_TASK_VEC.with_label_values(&[&name]);
+        let metrics_pending_task_high_water =
+            WORKER_PENDING_TASK_HIGH_WATER_VEC.with_label_values(&[&name]);
         self.start_with_timer_impl(
+            &name,
             runner,
             tx.clone(),
             rx,
@@ -399,12 +583,15 @@ impl Worker {
         Scheduler::new(
             tx,
             self.counter.clone(),
+            self.high_water.clone(),
             self.pending_capacity,
             metrics_pending_task_count,
+            metrics_pending_task_high_water,
+            self.waiters.clone(),
         )
     }
 
-    pub fn spawn_interval_task<F>(&self, interval: Duration, mut func: F)
+    pub fn spawn_interval_task<F>(&self, interval: Duration, mut func: F) -> TaskHandle
     where
         F: FnMut() + Send + 'static,
     {
@@ -412,16 +599,22 @@ impl Worker {
             .interval(std::time::Instant::now(), interval)
             .compat();
         let stop = self.stop.clone();
+        let (handle, cancelled, _waker, done_tx, id) = self.track_task();
+        let worker = self.clone();
         let _ = self.pool.spawn(async move {
             while !stop.load(Ordering::Relaxed)
+                && !cancelled.load(Ordering::Relaxed)
                 && let Some(Ok(_)) = interval.next().await
             {
                 func();
             }
+            worker.untrack_task(id);
+            let _ = done_tx.send(());
         });
+        handle
     }
 
-    pub fn spawn_interval_async_task<F, Fut>(&self, interval: Duration, mut func: F)
+    pub fn spawn_interval_async_task<F, Fut>(&self, interval: Duration, mut func: F) -> TaskHandle
     where
         Fut: Future<Output = ()> + Send + 'static,
         F: FnMut() -> Fut + Send + 'static,
@@ -430,21 +623,92 @@ impl Worker {
             .interval(std::time::Instant::now(), interval)
             .compat();
         let stop = self.stop.clone();
+        let (handle, cancelled, _waker, done_tx, id) = self.track_task();
+        let worker = self.clone();
         let _ = self.pool.spawn(async move {
             while !stop.load(Ordering::Relaxed)
+                && !cancelled.load(Ordering::Relaxed)
                 && let Some(Ok(_)) = interval.next().await
             {
                 let fut = func();
                 fut.await;
             }
+            worker.untrack_task(id);
+            let _ = done_tx.send(());
         });
+        handle
     }
 
-    pub fn spawn_async_task<F>(&self, f: F)
+    pub fn spawn_async_task<F>(&self, f: F) -> TaskHandle
     where
         F: Future<Output = ()> + Send + 'static,
     {
-        let _ = self.pool.spawn(f);
+        let (handle, cancelled, waker, done_tx, id) = self.track_task();
+        let worker = self.clone();
+        let _ = self.pool.spawn(async move {
+            // `f` itself doesn't get polled once cancellation is observed,
+            // cooperatively tearing the task down at its next yield point.
+            // Recording `cx`'s waker on every poll is what lets `cancel`
+            // force this yield point to happen right away, rather than
+            // waiting on whatever `f` itself is parked on.
+            let mut f = Box::pin(f);
+            futures::future::poll_fn(move |cx| {
+                *waker.lock().unwrap() = Some(cx.waker().clone());
+                if cancelled.load(Ordering::Relaxed) {
+                    return std::task::Poll::Ready(());
+                }
+                f.as_mut().poll(cx)
+            })
+            .await;
+            worker.untrack_task(id);
+            let _ = done_tx.send(());
+        });
+        handle
+    }
+
+    /// Registers a new task with this worker's cancellation/teardown
+    /// registry, returning the handle given to the caller alongside the
+    /// pieces the spawned future itself needs to observe cancellation,
+    /// report completion, and (via `untrack_task`) remove its own entry
+    /// once it finishes.
+    #[allow(clippy::type_complexity)]
+    fn track_task(
+        &self,
+    ) -> (
+        TaskHandle,
+        Arc<AtomicBool>,
+        Arc<Mutex<Option<Waker>>>,
+        futures::channel::oneshot::Sender<()>,
+        u64,
+    ) {
+        let id = self.next_task_id.fetch_add(1, Ordering::Relaxed);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let waker = Arc::new(Mutex::new(None));
+        let (done_tx, done_rx) = futures::channel::oneshot::channel();
+        let done = Arc::new(Mutex::new(Some(done_rx)));
+        self.tasks
+            .lock()
+            .unwrap()
+            .push((id, cancelled.clone(), waker.clone(), done.clone()));
+        (
+            TaskHandle {
+                cancelled: cancelled.clone(),
+                waker: waker.clone(),
+                done,
+            },
+            cancelled,
+            waker,
+            done_tx,
+            id,
+        )
+    }
+
+    /// Removes a task's entry from the registry once it has finished
+    /// running on its own, rather than via `stop()`. Without this, every
+    /// task a long-lived `Worker` ever spawns (e.g. for Raft apply or GC)
+    /// would leak its registry slot for the life of the process.
+    fn untrack_task(&self, id: u64) {
+        self.tasks.lock().unwrap().retain(|(task_id, ..)| *task_id != id);
     }
 
     fn delay_notify<T: Display + Send + 'static>(
@@ -472,23 +736,40 @@ impl Worker {
         let name = name.into();
         let metrics_pending_task_count = WORKER_PENDING_TASK_VEC.with_label_values(&[&name]);
         let metrics_handled_task_count = WORKER_HANDLED_TASK_VEC.with_label_values(&[&name]);
+        let metrics_pending_task_high_water =
+            WORKER_PENDING_TASK_HIGH_WATER_VEC.with_label_values(&[&name]);
         LazyWorker {
+            name,
             receiver: Some(rx),
             worker: self.clone(),
             scheduler: Scheduler::new(
                 tx,
                 self.counter.clone(),
+                self.high_water.clone(),
                 self.pending_capacity,
                 metrics_pending_task_count.clone(),
+                metrics_pending_task_high_water,
+                self.waiters.clone(),
             ),
             metrics_pending_task_count,
             metrics_handled_task_count,
         }
     }
 
-    /// Stops the worker thread.
+    /// Stops the worker thread, cooperatively cancelling every task spawned
+    /// via `spawn_async_task`/`spawn_interval_task`/`spawn_interval_async_task`
+    /// and waiting for each to finish tearing down before returning.
     pub fn stop(&self) {
         self.stop.store(true, Ordering::Release);
+        for (_id, cancelled, waker, done) in self.tasks.lock().unwrap().drain(..) {
+            cancelled.store(true, Ordering::Relaxed);
+            if let Some(waker) = waker.lock().unwrap().take() {
+                waker.wake();
+            }
+            if let Some(done) = done.lock().unwrap().take() {
+                let _ = block_on(done);
+            }
+        }
         self.pool.shutdown();
     }
 
@@ -512,6 +793,7 @@ impl Worker {
 
     fn start_with_timer_impl<R>(
         &self,
+        name: &str,
         runner: R,
         tx: UnboundedSender<Msg<R::Task>>,
         mut receiver: UnboundedReceiver<Msg<R::Task>>,
@@ -521,18 +803,29 @@ impl Worker {
         R: RunnableWithTimer + 'static,
     {
         let counter = self.counter.clone();
+        let waiters = self.waiters.clone();
         let timeout = runner.get_interval();
         let tx = if !timeout.is_zero() { Some(tx) } else { None };
         Self::delay_notify(tx.clone(), timeout);
+        let throttle = self.throttle;
+        let metrics_poll_duration = WORKER_POLL_DURATION_VEC.with_label_values(&[name]);
+        let metrics_wait_duration = WORKER_WAIT_DURATION_VEC.with_label_values(&[name]);
+        let metrics_busy_seconds = WORKER_BUSY_SECONDS_VEC.with_label_values(&[name]);
         let _ = self.pool.spawn(async move {
             let mut handle = RunnableWrapper { inner: runner };
-            while let Some(msg) = receiver.next().await {
+            let handle_msg = |handle: &mut RunnableWrapper<R>, msg: Msg<R::Task>, tx: &Option<_>| {
                 match msg {
-                    Msg::Task(task) => {
+                    Msg::Task(task, enqueued_at) => {
+                        metrics_wait_duration.observe(enqueued_at.elapsed().as_secs_f64());
+                        let started_at = Instant::now();
                         handle.inner.run(task);
+                        let poll_duration = started_at.elapsed();
+                        metrics_poll_duration.observe(poll_duration.as_secs_f64());
+                        metrics_busy_seconds.inc_by(poll_duration.as_secs_f64());
                         counter.fetch_sub(1, Ordering::SeqCst);
                         metrics_pending_task_count.dec();
                         metrics_handled_task_count.inc();
+                        wake_waiters(&waiters);
                     }
                     Msg::Timeout => {
                         handle.inner.on_timeout();
@@ -540,6 +833,37 @@ impl Worker {
                         Self::delay_notify(tx.clone(), timeout);
                     }
                 }
+            };
+            match throttle {
+                None => {
+                    while let Some(msg) = receiver.next().await {
+                        handle_msg(&mut handle, msg, &tx);
+                    }
+                }
+                Some(quantum) => {
+                    let mut ticks = GLOBAL_TIMER_HANDLE
+                        .interval(Instant::now() + quantum, quantum)
+                        .compat();
+                    loop {
+                        if ticks.next().await.is_none() {
+                            break;
+                        }
+                        let mut drained = false;
+                        while let Ok(Some(msg)) = receiver.try_next() {
+                            handle_msg(&mut handle, msg, &tx);
+                            drained = true;
+                        }
+                        if !drained {
+                            // The queue was empty on this tick: fall back to
+                            // an awaited recv so idle workers don't spin on
+                            // empty ticks and latency stays bounded.
+                            match receiver.next().await {
+                                Some(msg) => handle_msg(&mut handle, msg, &tx),
+                                None => break,
+                            }
+                        }
+                    }
+                }
             }
         });
     }
@@ -624,4 +948,244 @@ mod tests {
         // Handled task must be 3.
         assert_eq!(3, worker.metrics_handled_task_count.get());
     }
+
+    #[test]
+    fn test_throttled_batch_drain() {
+        let worker = Builder::new("test_throttled_batch_drain")
+            .throttle(Duration::from_millis(50))
+            .create();
+        let tasks = Arc::new(Mutex::new(vec![]));
+        let count = Arc::new(AtomicU64::new(0));
+        let scheduler = worker.start(
+            "test_throttled_batch_drain",
+            StepRunner {
+                count: count.clone(),
+                timeout_duration: Duration::ZERO,
+                tasks: tasks.clone(),
+            },
+        );
+
+        for i in 0..5 {
+            scheduler.schedule(i).unwrap();
+        }
+        // Tasks scheduled within the same quantum should not have run yet.
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(tasks.lock().unwrap().is_empty());
+
+        // After the quantum elapses, every queued task should have drained
+        // in one batch.
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(5, tasks.lock().unwrap().len());
+
+        worker.stop();
+    }
+
+    #[test]
+    fn test_schedule_async_waits_for_room() {
+        let worker = Builder::new("test_schedule_async_waits_for_room")
+            .pending_capacity(1)
+            .create();
+        let tasks = Arc::new(Mutex::new(vec![]));
+        let count = Arc::new(AtomicU64::new(0));
+        let scheduler = worker.start(
+            "test_schedule_async_waits_for_room",
+            StepRunner {
+                count,
+                timeout_duration: Duration::ZERO,
+                tasks: tasks.clone(),
+            },
+        );
+
+        // The queue only has room for one task: a plain `schedule` of a
+        // second task must be rejected...
+        scheduler.schedule(1).unwrap();
+        scheduler.schedule(2).unwrap_err();
+
+        // ...but `schedule_async` should wait for the first task to drain
+        // and then succeed instead of failing.
+        block_on(scheduler.schedule_async(2)).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(vec![1, 2], *tasks.lock().unwrap());
+
+        worker.stop();
+    }
+
+    #[test]
+    fn test_scheduler_metrics_tracks_high_water() {
+        let worker = Builder::new("test_scheduler_metrics_tracks_high_water").create();
+        let tasks = Arc::new(Mutex::new(vec![]));
+        let count = Arc::new(AtomicU64::new(0));
+        let scheduler = worker.start(
+            "test_scheduler_metrics_tracks_high_water",
+            StepRunner {
+                count,
+                timeout_duration: Duration::ZERO,
+                tasks: tasks.clone(),
+            },
+        );
+
+        scheduler.schedule(1).unwrap();
+        scheduler.schedule(2).unwrap();
+        scheduler.schedule(3).unwrap();
+        let metrics = scheduler.metrics();
+        assert_eq!(3, metrics.pending_tasks_high_water);
+
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(0, scheduler.metrics().pending_tasks);
+        // The high-water mark should survive after the queue drains.
+        assert_eq!(3, scheduler.metrics().pending_tasks_high_water);
+
+        worker.stop();
+    }
+
+    #[test]
+    fn test_scheduler_sets_pending_task_high_water_gauge() {
+        let worker = Builder::new("test_scheduler_sets_pending_task_high_water_gauge").create();
+        let tasks = Arc::new(Mutex::new(vec![]));
+        let count = Arc::new(AtomicU64::new(0));
+        let scheduler = worker.start(
+            "test_scheduler_sets_pending_task_high_water_gauge",
+            StepRunner {
+                count,
+                timeout_duration: Duration::ZERO,
+                tasks: tasks.clone(),
+            },
+        );
+        let gauge = WORKER_PENDING_TASK_HIGH_WATER_VEC
+            .with_label_values(&["test_scheduler_sets_pending_task_high_water_gauge"]);
+
+        scheduler.schedule(1).unwrap();
+        scheduler.schedule(2).unwrap();
+        assert_eq!(2, gauge.get());
+
+        std::thread::sleep(Duration::from_millis(10));
+        scheduler.schedule(3).unwrap();
+        // Draining back down to 0 pending tasks must not pull the gauge back
+        // down with it: it tracks the high-water mark, not the live depth.
+        assert_eq!(2, gauge.get());
+
+        worker.stop();
+    }
+
+    #[test]
+    fn test_task_handle_cancel_stops_interval_task() {
+        let worker = Worker::new("test_task_handle_cancel_stops_interval_task");
+        let ticks = Arc::new(AtomicU64::new(0));
+        let ticks_clone = ticks.clone();
+        let handle = worker.spawn_interval_task(Duration::from_millis(20), move || {
+            ticks_clone.fetch_add(1, atomic::Ordering::SeqCst);
+        });
+
+        std::thread::sleep(Duration::from_millis(60));
+        handle.join();
+        let ticks_at_cancel = ticks.load(atomic::Ordering::SeqCst);
+        assert!(ticks_at_cancel > 0);
+
+        // No further ticks should land after join() returns.
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(ticks_at_cancel, ticks.load(atomic::Ordering::SeqCst));
+
+        worker.stop();
+    }
+
+    #[test]
+    fn test_cancel_wakes_task_parked_on_unrelated_future() {
+        let worker = Worker::new("test_cancel_wakes_task_parked_on_unrelated_future");
+        // A future that never resolves on its own, to stand in for a task
+        // parked on some external event nothing else will trigger.
+        let handle = worker.spawn_async_task(future::pending::<()>());
+
+        // Cancelling (and therefore `join`ing) must complete promptly
+        // instead of blocking forever on a future that never wakes itself.
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let join_thread = std::thread::spawn(move || {
+            handle.join();
+            let _ = done_tx.send(());
+        });
+        done_rx
+            .recv_timeout(Duration::from_secs(3))
+            .expect("join() should not hang on a never-resolving future");
+        join_thread.join().unwrap();
+
+        worker.stop();
+    }
+
+    #[test]
+    fn test_task_registry_entry_removed_on_natural_completion() {
+        let worker = Worker::new("test_task_registry_entry_removed_on_natural_completion");
+        let handle = worker.spawn_async_task(future::ready(()));
+
+        // The task finishes on its own, without ever being cancelled or
+        // joined; its registry slot must still be freed, not only drained
+        // by `stop()`.
+        for _ in 0..100 {
+            if worker.tasks.lock().unwrap().is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(
+            worker.tasks.lock().unwrap().is_empty(),
+            "a naturally-completed task must remove its own registry entry"
+        );
+        drop(handle);
+
+        worker.stop();
+    }
+
+    #[test]
+    fn test_schedule_with_backoff_retries_then_succeeds() {
+        let worker = Builder::new("test_schedule_with_backoff_retries_then_succeeds")
+            .pending_capacity(1)
+            .create();
+        let tasks = Arc::new(Mutex::new(vec![]));
+        let count = Arc::new(AtomicU64::new(0));
+        let scheduler = worker.start(
+            "test_schedule_with_backoff_retries_then_succeeds",
+            StepRunner {
+                count,
+                timeout_duration: Duration::ZERO,
+                tasks: tasks.clone(),
+            },
+        );
+
+        scheduler.schedule(1).unwrap();
+        block_on(scheduler.schedule_with_backoff(
+            2,
+            Duration::from_millis(5),
+            Duration::from_millis(20),
+            10,
+        ))
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(vec![1, 2], *tasks.lock().unwrap());
+
+        worker.stop();
+    }
+
+    #[test]
+    fn test_schedule_with_backoff_gives_up_after_max_retries() {
+        // A scheduler whose receiver is never drained, with capacity for
+        // exactly one task, so the queue stays full for every retry.
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let scheduler: Scheduler<u64> = Scheduler::new(
+            tx,
+            Arc::new(AtomicUsize::new(0)),
+            Arc::new(AtomicUsize::new(0)),
+            1,
+            WORKER_PENDING_TASK_VEC.with_label_values(&["dummy_backoff"]),
+            WORKER_PENDING_TASK_HIGH_WATER_VEC.with_label_values(&["dummy_backoff"]),
+            Arc::new(Mutex::new(Vec::new())),
+        );
+        let _rx = rx;
+
+        scheduler.schedule(1).unwrap();
+        let err = block_on(scheduler.schedule_with_backoff(
+            2,
+            Duration::from_millis(1),
+            Duration::from_millis(2),
+            3,
+        ));
+        assert!(matches!(err, Err(ScheduleError::Full(2))));
+    }
 }
@@ -0,0 +1,110 @@
+// Copyright 2026 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! In-memory snapshots of lock-manager state, read by `StatusServer`'s
+//! `/lock_manager/*` diagnostic endpoints.
+//!
+//! This mirrors the existing metrics in [`super::metrics`]: rather than
+//! threading a live handle through every caller that might want a look at
+//! waiter-manager state, state is published into a process-wide snapshot
+//! that's cheap to read and never blocks the hot path. The snapshots change
+//! only through the `set_*` methods, and the waiter manager and deadlock
+//! detector do not call them, so the endpoints report an empty waiter list,
+//! an empty wait-for graph and a default detector status.
+
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+/// A single transaction blocked waiting to acquire a lock.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WaiterInfo {
+    pub txn_id: u64,
+    pub wait_for_txn_id: u64,
+    pub key_hash: u64,
+    pub key: Vec<u8>,
+}
+
+/// One edge of the deadlock detector's wait-for graph.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WaitForEntry {
+    pub txn_id: u64,
+    pub wait_for_txn_id: u64,
+    pub key_hash: u64,
+}
+
+/// A summary of the deadlock detector's own health, independent of the
+/// waiters/wait-for graph it tracks.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DetectorStatus {
+    pub is_leader: bool,
+    pub leader_store_id: Option<u64>,
+    pub tracked_txn_count: usize,
+}
+
+/// Holds the latest snapshot of each piece of lock-manager state that the
+/// introspection endpoints expose. Updated through the `set_*` methods;
+/// read (never blocking a writer for long) by the status server.
+#[derive(Default)]
+pub struct LockManagerIntrospection {
+    waiters: RwLock<Vec<WaiterInfo>>,
+    wait_for_entries: RwLock<Vec<WaitForEntry>>,
+    detector_status: RwLock<DetectorStatus>,
+}
+
+impl LockManagerIntrospection {
+    pub fn set_waiters(&self, waiters: Vec<WaiterInfo>) {
+        *self.waiters.write().unwrap() = waiters;
+    }
+
+    pub fn snapshot_waiters(&self) -> Vec<WaiterInfo> {
+        self.waiters.read().unwrap().clone()
+    }
+
+    pub fn set_wait_for_entries(&self, entries: Vec<WaitForEntry>) {
+        *self.wait_for_entries.write().unwrap() = entries;
+    }
+
+    pub fn snapshot_wait_for_entries(&self) -> Vec<WaitForEntry> {
+        self.wait_for_entries.read().unwrap().clone()
+    }
+
+    pub fn set_detector_status(&self, status: DetectorStatus) {
+        *self.detector_status.write().unwrap() = status;
+    }
+
+    pub fn snapshot_detector_status(&self) -> DetectorStatus {
+        self.detector_status.read().unwrap().clone()
+    }
+}
+
+lazy_static! {
+    pub static ref LOCK_MANAGER_INTROSPECTION: LockManagerIntrospection =
+        LockManagerIntrospection::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_reflects_latest_update() {
+        let introspection = LockManagerIntrospection::default();
+        assert!(introspection.snapshot_waiters().is_empty());
+
+        introspection.set_waiters(vec![WaiterInfo {
+            txn_id: 1,
+            wait_for_txn_id: 2,
+            key_hash: 3,
+            key: b"k".to_vec(),
+        }]);
+        assert_eq!(introspection.snapshot_waiters().len(), 1);
+
+        introspection.set_detector_status(DetectorStatus {
+            is_leader: true,
+            leader_store_id: Some(1),
+            tracked_txn_count: 1,
+        });
+        assert!(introspection.snapshot_detector_status().is_leader);
+    }
+}
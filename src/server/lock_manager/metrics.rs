@@ -35,6 +35,18 @@ make_static_metric! {
             txns,
         },
     }
+
+    pub struct CommitRoleCounterVec: IntCounter {
+        "role" => {
+            one_pc,
+            async_commit,
+            two_pc,
+        },
+        "outcome" => {
+            committed,
+            rolled_back,
+        },
+    }
 }
 
 lazy_static! {
@@ -67,6 +79,21 @@ lazy_static! {
         "Heartbeat of the leader of the deadlock detector"
     )
     .unwrap();
+    pub static ref COMMIT_ROLE_COUNTER_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_lock_manager_commit_role_counter",
+        "Total number of commits, by commit role and outcome",
+        &["role", "outcome"]
+    )
+    .unwrap();
+    pub static ref COMMIT_ROLE_COUNTER: CommitRoleCounterVec =
+        CommitRoleCounterVec::from(&COMMIT_ROLE_COUNTER_VEC);
+    pub static ref COMMIT_TS_SKEW_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_lock_manager_commit_ts_skew_duration",
+        "Bucketed skew between a transaction's commit_ts and lock_ts, in seconds \
+         of physical time",
+        exponential_buckets(0.0001, 2.0, 24).unwrap() // 0.1ms ~ 839s
+    )
+    .unwrap();
     pub static ref TASK_COUNTER_METRICS: LocalTaskCounter =
         auto_flush_from!(TASK_COUNTER_VEC, LocalTaskCounter);
     pub static ref ERROR_COUNTER_METRICS: LocalErrorCounter =
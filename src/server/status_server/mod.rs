@@ -0,0 +1,176 @@
+// Copyright 2020 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A small HTTP server, independent of the gRPC port, exposing operational
+//! endpoints: region metadata lookups and lock-manager introspection.
+
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use hyper::{
+    Body, Method, Request, Response, Server, StatusCode,
+    service::{make_service_fn, service_fn},
+};
+use raftstore::router::RaftExtension;
+use security::SecurityConfig;
+use service::service_manager::GrpcServiceManager;
+use tokio::{runtime::Runtime, sync::oneshot};
+
+use crate::{
+    config::ConfigController,
+    server::lock_manager::introspection::LOCK_MANAGER_INTROSPECTION,
+};
+
+/// Serves `/region/:id` and `/lock_manager/*` over plain HTTP, on its own
+/// listener separate from the gRPC service.
+///
+/// `cluster_id`, `cfg_controller`, `security_config` and `grpc_service_mgr`
+/// back other endpoints (config reload, TLS, pprof) not implemented here;
+/// kept on the struct so adding those doesn't change this constructor again.
+#[allow(dead_code)]
+pub struct StatusServer<R> {
+    cluster_id: u64,
+    cfg_controller: ConfigController,
+    security_config: Arc<SecurityConfig>,
+    router: R,
+    grpc_service_mgr: GrpcServiceManager,
+    rt: Runtime,
+    addr: Option<SocketAddr>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+}
+
+impl<R> StatusServer<R>
+where
+    R: RaftExtension + Clone + Send + Sync + 'static,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        cluster_id: u64,
+        cfg_controller: ConfigController,
+        security_config: Arc<SecurityConfig>,
+        router: R,
+        _store: Option<u64>,
+        grpc_service_mgr: GrpcServiceManager,
+        _resource_manager: Option<()>,
+    ) -> std::io::Result<Self> {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        Ok(StatusServer {
+            cluster_id,
+            cfg_controller,
+            security_config,
+            router,
+            grpc_service_mgr,
+            rt,
+            addr: None,
+            shutdown_tx: None,
+        })
+    }
+
+    pub fn listening_addr(&self) -> SocketAddr {
+        self.addr.expect("status server not started")
+    }
+
+    pub fn start(&mut self, addr: String) -> std::io::Result<()> {
+        let listener = std::net::TcpListener::bind(&addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        self.addr = Some(local_addr);
+
+        let (tx, rx) = oneshot::channel::<()>();
+        self.shutdown_tx = Some(tx);
+
+        let router = self.router.clone();
+        self.rt.spawn(async move {
+            let make_svc = make_service_fn(move |_conn| {
+                let router = router.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req| {
+                        let router = router.clone();
+                        async move { Ok::<_, Infallible>(handle_request(req, router).await) }
+                    }))
+                }
+            });
+            let server = Server::from_tcp(listener)
+                .expect("failed to build status server from listener")
+                .serve(make_svc)
+                .with_graceful_shutdown(async {
+                    let _ = rx.await;
+                });
+            let _ = server.await;
+        });
+        Ok(())
+    }
+
+    pub fn stop(self) {
+        if let Some(tx) = self.shutdown_tx {
+            let _ = tx.send(());
+        }
+    }
+}
+
+async fn handle_request<R>(req: Request<Body>, router: R) -> Response<Body>
+where
+    R: RaftExtension,
+{
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/lock_manager/waiters") => {
+            json_response(&LOCK_MANAGER_INTROSPECTION.snapshot_waiters())
+        }
+        (&Method::GET, "/lock_manager/wait_for") => {
+            json_response(&LOCK_MANAGER_INTROSPECTION.snapshot_wait_for_entries())
+        }
+        (&Method::GET, "/lock_manager/detector") => {
+            json_response(&LOCK_MANAGER_INTROSPECTION.snapshot_detector_status())
+        }
+        (&Method::GET, path) if path.starts_with("/region/") => {
+            region_meta_response(&router, path).await
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+fn json_response<T: serde::Serialize>(value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(_) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+/// Parses `/region/:id` and looks the region up through the raft router
+/// passed in at construction, the same one the gRPC debug service uses.
+async fn region_meta_response<R: RaftExtension>(router: &R, path: &str) -> Response<Body> {
+    let region_id = match path
+        .strip_prefix("/region/")
+        .and_then(|id| id.parse::<u64>().ok())
+    {
+        Some(region_id) => region_id,
+        None => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+    match router.query_region(region_id).await {
+        Ok(meta) => json_response(&meta),
+        Err(_) => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
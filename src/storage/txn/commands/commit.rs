@@ -3,6 +3,7 @@
 // #[PerformanceCriticalPath]
 use txn_types::{CommitRole, Key};
 
+use crate::server::lock_manager::metrics::{COMMIT_ROLE_COUNTER, COMMIT_TS_SKEW_HISTOGRAM};
 use crate::storage::{
     ProcessResult, Snapshot, TxnStatus,
     kv::WriteData,
@@ -15,9 +16,20 @@ use crate::storage::{
             WriteCommand, WriteContext, WriteResult,
         },
         commit,
+        quota::{keyspace_of, KEYSPACE_QUOTA_MANAGER},
     },
 };
 
+/// Maps the `Option<CommitRole>` the command carries onto the
+/// `COMMIT_ROLE_COUNTER`'s `role` label.
+fn commit_role_label(role: Option<CommitRole>) -> &'static str {
+    match role {
+        None | Some(CommitRole::TwoPc) => "two_pc",
+        Some(CommitRole::OnePc) => "one_pc",
+        Some(CommitRole::AsyncCommit) => "async_commit",
+    }
+}
+
 command! {
     /// Commit the transaction that started at `lock_ts`.
     ///
@@ -64,6 +76,9 @@ impl<S: Snapshot, L: LockManager> WriteCommand<S, L> for Commit {
         );
 
         let rows = self.keys.len();
+        let write_bytes: usize = self.keys.iter().map(|k| k.as_encoded().len()).sum();
+        let keyspace = self.keys.first().map(keyspace_of);
+
         // Pessimistic txn needs key_hashes to wake up waiters
         let mut released_locks = ReleasedLocks::new();
         for k in self.keys {
@@ -76,6 +91,27 @@ impl<S: Snapshot, L: LockManager> WriteCommand<S, L> for Commit {
             )?);
         }
 
+        // Charge the keyspace quota only after every key in this batch has
+        // applied its write locally; this is a best-effort accounting step,
+        // not a durability guarantee, since nothing here is persisted until
+        // the write is raft-applied. A later failure past this point still
+        // drifts the charge from what's actually committed; that drift is
+        // reconciled by KeyspaceQuotaManager::reconcile, not undone here.
+        if let Some(keyspace) = keyspace {
+            KEYSPACE_QUOTA_MANAGER
+                .try_charge(keyspace, write_bytes as u64, rows as u64)
+                .map_err(|e| Error::from(ErrorInner::Other(Box::new(e))))?;
+        }
+
+        let role_label = commit_role_label(self.commit_role);
+        match role_label {
+            "one_pc" => COMMIT_ROLE_COUNTER.one_pc.committed.inc(),
+            "async_commit" => COMMIT_ROLE_COUNTER.async_commit.committed.inc(),
+            _ => COMMIT_ROLE_COUNTER.two_pc.committed.inc(),
+        }
+        let skew_millis = self.commit_ts.physical().saturating_sub(self.lock_ts.physical());
+        COMMIT_TS_SKEW_HISTOGRAM.observe(skew_millis as f64 / 1000.0);
+
         let pr = ProcessResult::TxnStatus {
             txn_status: TxnStatus::committed(self.commit_ts),
         };
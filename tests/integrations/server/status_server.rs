@@ -30,6 +30,64 @@ async fn check(authority: SocketAddr, region_id: u64) -> Result<(), Box<dyn Erro
     Ok(())
 }
 
+async fn check_lock_manager_endpoint(
+    authority: SocketAddr,
+    path: &str,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    let client = Client::new();
+    let uri = Uri::builder()
+        .scheme("http")
+        .authority(authority.to_string().as_str())
+        .path_and_query(path)
+        .build()?;
+    let resp = client.get(uri).await?;
+    let (parts, raw_body) = resp.into_parts();
+    let body = body::to_bytes(raw_body).await?;
+    assert_eq!(
+        StatusCode::OK,
+        parts.status,
+        "{}",
+        String::from_utf8(body.to_vec())?
+    );
+    assert_eq!("application/json", parts.headers["content-type"].to_str()?);
+    Ok(serde_json::from_slice(body.as_ref())?)
+}
+
+#[test]
+fn test_lock_manager_introspection_endpoints() {
+    let mut cluster = new_server_cluster(0, 1);
+    cluster.run();
+    let region = cluster.get_region(b"");
+    let store_id = region.get_peers().first().unwrap().get_store_id();
+    let router = cluster.raft_extension(store_id);
+    let mut status_server = StatusServer::new(
+        1,
+        ConfigController::default(),
+        Arc::new(SecurityConfig::default()),
+        router,
+        None,
+        GrpcServiceManager::dummy(),
+        None,
+    )
+    .unwrap();
+    let addr = format!("127.0.0.1:{}", test_util::alloc_port());
+    status_server.start(addr).unwrap();
+    let authority = status_server.listening_addr();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    for path in [
+        "/lock_manager/waiters",
+        "/lock_manager/wait_for",
+        "/lock_manager/detector",
+    ] {
+        let resp = rt.block_on(check_lock_manager_endpoint(authority, path));
+        if let Err(err) = resp {
+            panic!("{}: {}", path, err);
+        }
+    }
+    status_server.stop();
+}
+
 #[test]
 fn test_region_meta_endpoint() {
     let mut cluster = new_server_cluster(0, 1);